@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS pagination_tokens (
+    token TEXT PRIMARY KEY,
+    page INT NOT NULL,
+    per_page BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
\ No newline at end of file
@@ -0,0 +1,6 @@
+ALTER TABLE pagination_tokens
+    ADD COLUMN IF NOT EXISTS cursor_created_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS cursor_id UUID,
+    ADD COLUMN IF NOT EXISTS backward BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS quotes_created_at_id_idx ON quotes (created_at, id);
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE pagination_tokens
+    ADD COLUMN IF NOT EXISTS author_pattern TEXT,
+    ADD COLUMN IF NOT EXISTS quote_pattern TEXT;
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- カーソルを並び順に依存しないテキスト形式に変更するため、既存のトークンは破棄する
+DELETE FROM pagination_tokens;
+
+ALTER TABLE pagination_tokens
+    DROP COLUMN IF EXISTS cursor_created_at,
+    ADD COLUMN IF NOT EXISTS cursor_value TEXT,
+    ADD COLUMN IF NOT EXISTS sort TEXT NOT NULL DEFAULT 'created_at',
+    ADD COLUMN IF NOT EXISTS descending BOOLEAN NOT NULL DEFAULT FALSE;
\ No newline at end of file
@@ -0,0 +1,13 @@
+-- 引用を削除しても履歴を残せるよう、quotesへの外部キーは張らない
+CREATE TABLE IF NOT EXISTS quote_revisions (
+    quote_id UUID NOT NULL,
+    version INT NOT NULL,
+    author TEXT NOT NULL,
+    quote TEXT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (quote_id, version)
+);
+
+-- 既存の引用は現在の内容を最初の履歴として記録する
+INSERT INTO quote_revisions (quote_id, version, author, quote, recorded_at)
+SELECT id, version, author, quote, created_at FROM quotes;
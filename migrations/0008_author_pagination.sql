@@ -0,0 +1 @@
+ALTER TABLE pagination_tokens ADD COLUMN IF NOT EXISTS author TEXT;
@@ -0,0 +1,5 @@
+ALTER TABLE quotes ADD COLUMN IF NOT EXISTS tsv tsvector
+    GENERATED ALWAYS AS (to_tsvector('english', quote || ' ' || author)) STORED;
+CREATE INDEX IF NOT EXISTS quotes_tsv_idx ON quotes USING GIN (tsv);
+
+ALTER TABLE pagination_tokens ADD COLUMN IF NOT EXISTS fts_query TEXT;
@@ -0,0 +1,4 @@
+-- トークンを発行したエンドポイントを記録する。どのエンドポイント用か分からない既存のトークンは破棄する
+DELETE FROM pagination_tokens;
+
+ALTER TABLE pagination_tokens ADD COLUMN IF NOT EXISTS endpoint TEXT NOT NULL;
@@ -0,0 +1,4 @@
+-- 同じキーが別の内容で再利用されたことを検出するため、最初のリクエスト内容を保存する
+ALTER TABLE idempotency_keys ADD COLUMN IF NOT EXISTS author TEXT;
+ALTER TABLE idempotency_keys ADD COLUMN IF NOT EXISTS quote TEXT;
+CREATE INDEX IF NOT EXISTS idempotency_keys_created_at_idx ON idempotency_keys (created_at);
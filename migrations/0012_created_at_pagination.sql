@@ -0,0 +1,2 @@
+ALTER TABLE pagination_tokens ADD COLUMN IF NOT EXISTS created_after TIMESTAMPTZ;
+ALTER TABLE pagination_tokens ADD COLUMN IF NOT EXISTS created_before TIMESTAMPTZ;
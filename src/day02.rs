@@ -0,0 +1,474 @@
+use crate::AppState;
+use axum::{
+    body::Body,
+    extract::Query,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::net::Ipv6Addr;
+use utoipa::OpenApi;
+
+const MAX_V6_STREAM_LINE_BYTES: usize = 256;
+
+#[derive(Deserialize)]
+struct Addresses {
+    from: String,
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct Addresses2 {
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+struct Addresses3 {
+    to: String,
+    key: String,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum KeyMode {
+    #[default]
+    Wrapping,
+    Saturating,
+}
+
+impl KeyMode {
+    fn sub_octet(self, to: u8, from: u8) -> u8 {
+        match self {
+            KeyMode::Wrapping => to.wrapping_sub(from),
+            KeyMode::Saturating => to.saturating_sub(from),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct KeyModeQuery {
+    #[serde(default)]
+    mode: KeyMode,
+}
+
+// "."区切りの4つのu8に変換する
+fn parse_ipv4_address(address: &str) -> Result<[u8; 4], (StatusCode, String)> {
+    let invalid = || {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid IPv4 address: {}", address),
+        )
+    };
+    let mut octets = [0u8; 4];
+    let mut parts = address.split('.');
+    for octet in octets.iter_mut() {
+        *octet = parts
+            .next()
+            .and_then(|s| s.parse::<u8>().ok())
+            .ok_or_else(invalid)?;
+    }
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(octets)
+}
+
+fn format_ipv4_address(octets: impl Iterator<Item = u8>) -> String {
+    octets
+        .map(|octet| octet.to_string())
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+#[utoipa::path(
+    get,
+    path = "/2/dest",
+    tag = "addresses",
+    params(
+        ("from" = String, Query, description = "IPv4 source"),
+        ("key" = String, Query, description = "IPv4 key"),
+    ),
+    responses(
+        (status = 200, description = "The computed address", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid address", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn calc_dest_address(addresses: Query<Addresses>) -> Result<String, (StatusCode, String)> {
+    let from_parts = parse_ipv4_address(&addresses.from)?;
+    let key_parts = parse_ipv4_address(&addresses.key)?;
+    // wrapping_add every part of the from_parts and convert to string and concatenate with "."
+    Ok(format_ipv4_address(
+        from_parts
+            .iter()
+            .zip(key_parts.iter())
+            .map(|(from, key)| from.wrapping_add(*key)),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/2/from",
+    tag = "addresses",
+    params(
+        ("to" = String, Query, description = "IPv4 destination"),
+        ("key" = String, Query, description = "IPv4 key"),
+    ),
+    responses(
+        (status = 200, description = "The computed address", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid address", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn calc_from_address(addresses: Query<Addresses3>) -> Result<String, (StatusCode, String)> {
+    let to_parts = parse_ipv4_address(&addresses.to)?;
+    let key_parts = parse_ipv4_address(&addresses.key)?;
+    // destの逆算なので、to_partsの各部分からkey_partsをwrapping_subする
+    Ok(format_ipv4_address(
+        to_parts
+            .iter()
+            .zip(key_parts.iter())
+            .map(|(to, key)| to.wrapping_sub(*key)),
+    ))
+}
+
+// 省略形も含めて標準ライブラリでパースする（不正な入力でpanicしない）
+fn parse_ipv6_address(address: &str) -> Result<Ipv6Addr, (StatusCode, String)> {
+    address.trim().parse::<Ipv6Addr>().map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("Invalid IPv6 address: {}", address),
+        )
+    })
+}
+
+// xorは自身が逆演算なので、dest・key・fromのどれを求めるのにも使える
+// 出力は標準ライブラリの表記（RFC 5952の圧縮形式）
+fn xor_ipv6_addresses(a: &str, b: &str) -> Result<String, (StatusCode, String)> {
+    let a = parse_ipv6_address(a)?;
+    let b = parse_ipv6_address(b)?;
+    Ok(Ipv6Addr::from(a.to_bits() ^ b.to_bits()).to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/2/v6/dest",
+    tag = "addresses",
+    params(
+        ("from" = String, Query, description = "IPv6 source"),
+        ("key" = String, Query, description = "IPv6 key"),
+    ),
+    responses(
+        (status = 200, description = "The computed address", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid address", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn calc_ipv6_dest_address(
+    addresses: Query<Addresses>,
+) -> Result<String, (StatusCode, String)> {
+    xor_ipv6_addresses(&addresses.from, &addresses.key)
+}
+
+// ストリーム用。不正な入力でもpanicしないよう標準ライブラリでパースする
+fn ipv6_dest_line(line: &[u8], line_number: usize) -> Option<String> {
+    let line = std::str::from_utf8(line).map(str::trim);
+    if line.is_ok_and(str::is_empty) {
+        return None;
+    }
+    let dest = line.ok().and_then(|line| {
+        let (from, key) = line.split_once(',')?;
+        let from = from.trim().parse::<Ipv6Addr>().ok()?;
+        let key = key.trim().parse::<Ipv6Addr>().ok()?;
+        Some(Ipv6Addr::from(from.to_bits() ^ key.to_bits()))
+    });
+    Some(match dest {
+        Some(dest) => format!("{}\n", dest),
+        None => format!("error: invalid line {}\n", line_number),
+    })
+}
+
+// 1行に"from,key"を書いたテキストを受け取り、読めた行から順にdestを返す。
+// 不正な行があってもストリームは止めず、その行の代わりに"error: invalid line <行番号>"を返す
+#[utoipa::path(
+    post,
+    path = "/2/v6/dest/stream",
+    tag = "addresses",
+    request_body(
+        content = String,
+        content_type = "text/plain",
+        description = "One \"from,key\" pair per line",
+    ),
+    responses((status = 200, description = "One destination (or error) per line, streamed", body = String, content_type = "text/plain")),
+)]
+async fn stream_ipv6_dest_addresses(body: Body) -> Response {
+    let mut chunks = body.into_data_stream();
+    let lines = async_stream::stream! {
+        let mut buffer = Vec::new();
+        let mut line_number = 0;
+        // 長すぎる行は改行が来るまで読み捨てる
+        let mut discarding = false;
+        loop {
+            while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=end).collect();
+                line_number += 1;
+                if discarding {
+                    discarding = false;
+                    yield Ok::<_, axum::Error>(format!("error: invalid line {}\n", line_number));
+                } else if let Some(output) = ipv6_dest_line(&line, line_number) {
+                    yield Ok(output);
+                }
+            }
+            if buffer.len() > MAX_V6_STREAM_LINE_BYTES {
+                buffer.clear();
+                discarding = true;
+            }
+            match chunks.next().await {
+                Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                Some(Err(e)) => {
+                    yield Err(e);
+                    return;
+                }
+                None => break,
+            }
+        }
+        // 最後の行は改行で終わっていなくてもよい
+        line_number += 1;
+        if discarding {
+            yield Ok(format!("error: invalid line {}\n", line_number));
+        } else if let Some(output) = ipv6_dest_line(&buffer, line_number) {
+            yield Ok(output);
+        }
+    };
+
+    (
+        [(CONTENT_TYPE, "text/plain; charset=utf-8")],
+        Body::from_stream(lines),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/2/key",
+    tag = "addresses",
+    params(
+        ("from" = String, Query, description = "IPv4 source"),
+        ("to" = String, Query, description = "IPv4 destination"),
+        ("mode" = Option<String>, Query, description = "wrapping (default) or saturating"),
+    ),
+    responses(
+        (status = 200, description = "The computed address", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid address", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn calc_key_address(
+    addresses: Query<Addresses2>,
+    Query(key_mode): Query<KeyModeQuery>,
+) -> Result<String, (StatusCode, String)> {
+    let from_parts = parse_ipv4_address(&addresses.from)?;
+    let to_parts = parse_ipv4_address(&addresses.to)?;
+    // modeに応じてto_partsの各部分からfrom_partsを引き、"."で連結する
+    Ok(format_ipv4_address(
+        to_parts
+            .iter()
+            .zip(from_parts.iter())
+            .map(|(to, from)| key_mode.mode.sub_octet(*to, *from)),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/2/v6/key",
+    tag = "addresses",
+    params(
+        ("from" = String, Query, description = "IPv6 source"),
+        ("to" = String, Query, description = "IPv6 destination"),
+    ),
+    responses(
+        (status = 200, description = "The computed address", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid address", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn calc_ipv6_key_address(
+    addresses: Query<Addresses2>,
+) -> Result<String, (StatusCode, String)> {
+    xor_ipv6_addresses(&addresses.to, &addresses.from)
+}
+
+#[utoipa::path(
+    get,
+    path = "/2/v6/from",
+    tag = "addresses",
+    params(
+        ("to" = String, Query, description = "IPv6 destination"),
+        ("key" = String, Query, description = "IPv6 key"),
+    ),
+    responses(
+        (status = 200, description = "The computed address", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid address", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn calc_ipv6_from_address(
+    addresses: Query<Addresses3>,
+) -> Result<String, (StatusCode, String)> {
+    xor_ipv6_addresses(&addresses.to, &addresses.key)
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    calc_dest_address,
+    calc_key_address,
+    calc_from_address,
+    calc_ipv6_dest_address,
+    stream_ipv6_dest_addresses,
+    calc_ipv6_key_address,
+    calc_ipv6_from_address,
+))]
+pub(crate) struct ApiDoc;
+
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/2/dest", get(calc_dest_address))
+        .route("/2/key", get(calc_key_address))
+        .route("/2/from", get(calc_from_address))
+        .route("/2/v6/dest", get(calc_ipv6_dest_address))
+        .route("/2/v6/dest/stream", post(stream_ipv6_dest_addresses))
+        .route("/2/v6/key", get(calc_ipv6_key_address))
+        .route("/2/v6/from", get(calc_ipv6_from_address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeyMode;
+    use crate::testing::*;
+    use axum::{body::Body, http::StatusCode};
+
+    #[test]
+    fn key_mode_wraps_or_saturates() {
+        assert_eq!(KeyMode::Wrapping.sub_octet(10, 20), 246);
+        assert_eq!(KeyMode::Saturating.sub_octet(10, 20), 0);
+        assert_eq!(KeyMode::Saturating.sub_octet(20, 10), 10);
+    }
+
+    #[tokio::test]
+    async fn key_route_honors_mode() {
+        let router = router(lazy_pool());
+        for (query, expected) in [
+            ("", "0.0.0.246"),
+            ("&mode=wrapping", "0.0.0.246"),
+            ("&mode=saturating", "0.0.0.0"),
+        ] {
+            let uri = format!("/2/key?from=0.0.0.20&to=0.0.0.10{}", query);
+            let response = send(&router, get(&uri)).await;
+            assert_eq!(response.status, StatusCode::OK);
+            assert_eq!(response.text(), expected, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn v6_endpoints_round_trip() {
+        let router = router(lazy_pool());
+        let from = "fe80::1";
+        let key = "5:6:7::3333";
+
+        let dest = send(
+            &router,
+            get(&format!("/2/v6/dest?from={}&key={}", from, key)),
+        )
+        .await;
+        assert_eq!(dest.status, StatusCode::OK);
+        let dest = dest.text();
+        assert_eq!(dest, "fe85:6:7::3332");
+
+        let response = send(
+            &router,
+            get(&format!("/2/v6/key?from={}&to={}", from, dest)),
+        )
+        .await;
+        assert_eq!(response.text(), key);
+        let response = send(&router, get(&format!("/2/v6/from?to={}&key={}", dest, key))).await;
+        assert_eq!(response.text(), from);
+    }
+
+    #[tokio::test]
+    async fn v6_endpoints_reject_invalid_addresses() {
+        let router = router(lazy_pool());
+        for uri in [
+            "/2/v6/from?to=zz&key=::",
+            "/2/v6/dest?from=::&key=1:2:3:4:5:6:7:8:9",
+            "/2/v6/key?from=fffff::&to=::",
+        ] {
+            let response = send(&router, get(uri)).await;
+            assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert!(
+                response.text().starts_with("Invalid IPv6 address"),
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn v4_endpoints_are_mutually_consistent() {
+        let router = router(lazy_pool());
+        let (from, key, dest) = ("10.200.3.250", "1.100.255.10", "11.44.2.4");
+
+        let response = send(&router, get(&format!("/2/dest?from={}&key={}", from, key))).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), dest);
+        let response = send(&router, get(&format!("/2/key?from={}&to={}", from, dest))).await;
+        assert_eq!(response.text(), key);
+        let response = send(&router, get(&format!("/2/from?to={}&key={}", dest, key))).await;
+        assert_eq!(response.text(), from);
+    }
+
+    #[tokio::test]
+    async fn v4_endpoints_reject_invalid_addresses() {
+        let router = router(lazy_pool());
+        for uri in [
+            "/2/dest?from=10.0.0.256&key=1.2.3.4",
+            "/2/key?from=10.0.0&to=1.2.3.4",
+            "/2/from?to=1.2.3.4.5&key=1.2.3.4",
+            "/2/from?to=a.b.c.d&key=1.2.3.4",
+        ] {
+            let response = send(&router, get(uri)).await;
+            assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert!(
+                response.text().starts_with("Invalid IPv4 address"),
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn v6_stream_answers_lines_in_order() {
+        let router = router(lazy_pool());
+        // 行の途中でチャンクが切れても1行として扱う
+        let chunks = ["::1,:", ":2\nnope\n", "\nfe80::1,::ff"]
+            .map(|chunk| Ok::<_, std::io::Error>(chunk.to_string()));
+        let body = Body::from_stream(futures::stream::iter(chunks));
+        let response = send(
+            &router,
+            request("POST", "/2/v6/dest/stream", Some("text/plain"), body),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "::3\nerror: invalid line 2\nfe80::fe\n");
+
+        let long = format!("{}\n::1,::2\n", "a".repeat(1000));
+        let response = send(
+            &router,
+            request(
+                "POST",
+                "/2/v6/dest/stream",
+                Some("text/plain"),
+                Body::from(long),
+            ),
+        )
+        .await;
+        assert_eq!(response.text(), "error: invalid line 1\n::3\n");
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{wants_json, AppState};
+use axum::{
+    body::Bytes,
+    extract::{Json, Query},
+    http::{
+        header::{HeaderMap, CONTENT_TYPE},
+        StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::post,
+    Router,
+};
+use cargo_manifest::{Manifest, MaybeInherited};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use serde_yaml::Value as YamlValue;
+use utoipa::OpenApi;
+
+fn manifest_to_toml(headers: &HeaderMap, body: &Bytes) -> Result<String, (StatusCode, String)> {
+    let content_type_header = headers.get(CONTENT_TYPE);
+    let content_type = match content_type_header {
+        Some(content_type_header) => content_type_header,
+        None => return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, String::new())),
+    };
+
+    if content_type == "application/json" {
+        // JSONをTOMLに変換
+        let json_value: JsonValue = match serde_json::from_slice(body) {
+            Ok(v) => v,
+            Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid JSON".to_string())),
+        };
+        toml::to_string_pretty(&json_value).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Failed to convert JSON to TOML".to_string(),
+            )
+        })
+    } else if content_type == "application/yaml" {
+        // YAMLをTOMLに変換
+        let yaml_value: YamlValue = match serde_yaml::from_slice(body) {
+            Ok(v) => v,
+            Err(_) => return Err((StatusCode::BAD_REQUEST, "Invalid YAML".to_string())),
+        };
+        toml::to_string_pretty(&yaml_value).map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "Failed to convert YAML to TOML".to_string(),
+            )
+        })
+    } else if content_type == "application/toml" {
+        String::from_utf8(body.to_vec())
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid TOML".to_string()))
+    } else {
+        Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, String::new()))
+    }
+}
+
+#[derive(Serialize)]
+struct SkippedOrder {
+    index: usize,
+    reason: &'static str,
+}
+
+#[derive(Serialize)]
+struct ManifestReport {
+    magic_keyword: bool,
+    valid_orders: usize,
+    skipped_orders: usize,
+    skipped: Vec<SkippedOrder>,
+    #[serde(skip)]
+    orders: Vec<String>,
+    // 数値として読めない文字列の数量があればリクエスト全体を400にする
+    #[serde(skip)]
+    invalid_quantity: bool,
+}
+
+fn analyze_manifest(manifest: Manifest) -> ManifestReport {
+    let mut report = ManifestReport {
+        magic_keyword: false,
+        valid_orders: 0,
+        skipped_orders: 0,
+        skipped: Vec::new(),
+        orders: Vec::new(),
+        invalid_quantity: false,
+    };
+
+    let package = match manifest.package {
+        Some(p) => p,
+        None => return report,
+    };
+
+    report.magic_keyword = match &package.keywords {
+        Some(MaybeInherited::Local(k)) => k.contains(&"Christmas 2024".to_string()),
+        _ => false,
+    };
+
+    let orders = match package
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get("orders"))
+        .and_then(|o| o.as_array())
+    {
+        Some(o) => o,
+        None => return report,
+    };
+
+    for (index, order) in orders.iter().enumerate() {
+        let item = match order.get("item") {
+            Some(i) => i,
+            None => {
+                report.skipped.push(SkippedOrder {
+                    index,
+                    reason: "missing item",
+                });
+                continue;
+            }
+        };
+        let quantity = match order.get("quantity") {
+            Some(q) => q,
+            None => {
+                report.skipped.push(SkippedOrder {
+                    index,
+                    reason: "missing quantity",
+                });
+                continue;
+            }
+        };
+        let item = match item.as_str() {
+            Some(i) => i,
+            None => {
+                report.skipped.push(SkippedOrder {
+                    index,
+                    reason: "item is not a string",
+                });
+                continue;
+            }
+        };
+        // 文字列の"3"も3として扱う
+        let quantity = match quantity
+            .as_integer()
+            .or_else(|| quantity.as_str().and_then(|q| q.trim().parse::<i64>().ok()))
+        {
+            Some(q) => q,
+            None if quantity.is_str() => {
+                report.invalid_quantity = true;
+                report.skipped.push(SkippedOrder {
+                    index,
+                    reason: "quantity is not a number",
+                });
+                continue;
+            }
+            None => {
+                report.skipped.push(SkippedOrder {
+                    index,
+                    reason: "quantity is not an integer",
+                });
+                continue;
+            }
+        };
+        report.orders.push(format!("{}: {}", item, quantity));
+    }
+    report.valid_orders = report.orders.len();
+    report.skipped_orders = report.skipped.len();
+    report
+}
+
+#[derive(Deserialize)]
+struct ManifestQuery {
+    dry_run: Option<bool>,
+    // 注文がないときのステータス。204を扱えないクライアント向けに200も選べる
+    empty: Option<u16>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/5/manifest",
+    tag = "manifest",
+    params(
+        ("dry_run" = Option<bool>, Query, description = "Return the analysis report instead"),
+        ("empty" = Option<u16>, Query, description = "Status for a manifest without orders: 204 (default) or 200"),
+    ),
+    request_body(
+        content((String = "application/toml"), (String = "application/yaml"), (String = "application/json")),
+        description = "A Cargo manifest",
+    ),
+    responses(
+        (status = 200, description = "One order per line (a JSON array with Accept: application/json and ?empty=200)", body = String, content_type = "text/plain"),
+        (status = 204, description = "No orders"),
+        (status = 400, description = "Invalid manifest or missing magic keyword", body = String, content_type = "text/plain"),
+        (status = 415, description = "Unsupported content type"),
+    ),
+)]
+async fn parse_manifest(
+    Query(query): Query<ManifestQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    if !matches!(query.empty, None | Some(200) | Some(204)) {
+        return (StatusCode::BAD_REQUEST, "empty must be 200 or 204").into_response();
+    }
+    let toml_str = match manifest_to_toml(&headers, &body) {
+        Ok(s) => s,
+        Err(e) => return e.into_response(),
+    };
+
+    let manifest = match Manifest::from_slice(toml_str.as_bytes()) {
+        Ok(m) => m,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid manifest").into_response(),
+    };
+
+    let report = analyze_manifest(manifest);
+    // dry_runの場合は処理内容のレポートだけ返す
+    if query.dry_run == Some(true) {
+        return Json(report).into_response();
+    }
+
+    if !report.magic_keyword {
+        return (StatusCode::BAD_REQUEST, "Magic keyword not provided").into_response();
+    }
+    if report.invalid_quantity {
+        return (StatusCode::BAD_REQUEST, "Invalid quantity").into_response();
+    }
+    if report.orders.is_empty() {
+        return match query.empty {
+            Some(200) if wants_json(&headers) => Json(report.orders).into_response(),
+            Some(200) => StatusCode::OK.into_response(),
+            _ => StatusCode::NO_CONTENT.into_response(),
+        };
+    }
+    (StatusCode::OK, report.orders.join("\n")).into_response()
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(parse_manifest,))]
+pub(crate) struct ApiDoc;
+
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new().route("/5/manifest", post(parse_manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::*;
+    use axum::http::StatusCode;
+
+    const MANIFEST_HEADER: &str = r#"
+[package]
+name = "not-a-gift-order"
+authors = ["Not Santa"]
+keywords = ["Christmas 2024"]
+"#;
+
+    fn manifest_with(orders: &str) -> String {
+        format!("{}{}", MANIFEST_HEADER, orders)
+    }
+
+    #[tokio::test]
+    async fn dry_run_reports_valid_and_skipped_orders() {
+        let router = router(lazy_pool());
+        let manifest = manifest_with(
+            r#"
+[[package.metadata.orders]]
+item = "Toy car"
+quantity = 2
+
+[[package.metadata.orders]]
+quantity = 1
+
+[[package.metadata.orders]]
+item = "Lego brick"
+quantity = 1.5
+
+[[package.metadata.orders]]
+item = "Snow globe"
+quantity = 3
+"#,
+        );
+        let response = send(
+            &router,
+            post(
+                "/5/manifest?dry_run=true",
+                "application/toml",
+                manifest.clone(),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json(),
+            serde_json::json!({
+                "magic_keyword": true,
+                "valid_orders": 2,
+                "skipped_orders": 2,
+                "skipped": [
+                    { "index": 1, "reason": "missing item" },
+                    { "index": 2, "reason": "quantity is not an integer" },
+                ],
+            })
+        );
+
+        let response = send(&router, post("/5/manifest", "application/toml", manifest)).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "Toy car: 2\nSnow globe: 3");
+    }
+
+    #[tokio::test]
+    async fn string_quantities() {
+        let router = router(lazy_pool());
+        let manifest = manifest_with(
+            r#"
+[[package.metadata.orders]]
+item = "Toy car"
+quantity = "5"
+"#,
+        );
+        let response = send(&router, post("/5/manifest", "application/toml", manifest)).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "Toy car: 5");
+
+        let manifest = manifest_with(
+            r#"
+[[package.metadata.orders]]
+item = "Toy car"
+quantity = "lots"
+"#,
+        );
+        let response = send(&router, post("/5/manifest", "application/toml", manifest)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.text(), "Invalid quantity");
+    }
+
+    #[tokio::test]
+    async fn empty_manifest_status_follows_query() {
+        let router = router(lazy_pool());
+        let manifest = manifest_with("");
+
+        let response = send(
+            &router,
+            post("/5/manifest", "application/toml", manifest.clone()),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::NO_CONTENT);
+
+        let response = send(
+            &router,
+            post(
+                "/5/manifest?empty=200",
+                "application/toml",
+                manifest.clone(),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "");
+
+        let mut request = post(
+            "/5/manifest?empty=200",
+            "application/toml",
+            manifest.clone(),
+        );
+        request
+            .headers_mut()
+            .insert("accept", "application/json".parse().unwrap());
+        let response = send(&router, request).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json(), serde_json::json!([]));
+
+        let response = send(
+            &router,
+            post("/5/manifest?empty=500", "application/toml", manifest),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.text(), "empty must be 200 or 204");
+    }
+}
@@ -0,0 +1,264 @@
+use crate::AppState;
+use axum::{
+    extract::{rejection::JsonRejection, Json, State},
+    http::{
+        header::{HeaderMap, CONTENT_TYPE},
+        HeaderValue, StatusCode,
+    },
+    routing::{get, post},
+    Router,
+};
+use leaky_bucket::RateLimiter;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::{OpenApi, ToSchema};
+
+pub(crate) const BUCKET_SIZE: usize = 5;
+pub(crate) const REFILL_INTERVAL: u64 = 1;
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum Volume {
+    Gallons(f32),
+    Liters(f32),
+    Pints(f32),
+    Litres(f32),
+}
+
+fn convert_volume(volume: Volume) -> Volume {
+    match volume {
+        Volume::Gallons(v) => Volume::Liters(v * 3.785_411_8),
+        Volume::Liters(v) => Volume::Gallons(v / 3.785_411_8),
+        Volume::Pints(v) => Volume::Litres(v * 0.56826125),
+        Volume::Litres(v) => Volume::Pints(v / 0.56826125),
+    }
+}
+
+impl Volume {
+    fn unit(&self) -> &'static str {
+        match self {
+            Volume::Gallons(_) => "gallons",
+            Volume::Liters(_) => "liters",
+            Volume::Pints(_) => "pints",
+            Volume::Litres(_) => "litres",
+        }
+    }
+
+    fn value(&self) -> f32 {
+        match self {
+            Volume::Gallons(v) | Volume::Liters(v) | Volume::Pints(v) | Volume::Litres(v) => *v,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+struct VolumeUnit {
+    unit: &'static str,
+    target: &'static str,
+    factor: f32,
+}
+
+// convert_volumeに1を渡した結果から作るので、変換の定義とずれない
+#[utoipa::path(
+    get,
+    path = "/9/units",
+    tag = "milk",
+    responses((status = 200, description = "Conversion factor for each unit", body = Vec<VolumeUnit>)),
+)]
+async fn list_volume_units() -> Json<Vec<VolumeUnit>> {
+    let units = [
+        Volume::Gallons(1.0),
+        Volume::Liters(1.0),
+        Volume::Pints(1.0),
+        Volume::Litres(1.0),
+    ]
+    .into_iter()
+    .map(|volume| {
+        let unit = volume.unit();
+        let converted = convert_volume(volume);
+        VolumeUnit {
+            unit,
+            target: converted.unit(),
+            factor: converted.value(),
+        }
+    })
+    .collect();
+    Json(units)
+}
+
+#[utoipa::path(
+    post,
+    path = "/9/milk",
+    tag = "milk",
+    request_body(
+        content = Option<Volume>,
+        description = "With application/json, the volume to convert",
+    ),
+    responses(
+        (status = 200, description = "\"Milk withdrawn\", or the converted volume as JSON", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid volume"),
+        (status = 429, description = "No milk available", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn withdraw_milk(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    volume: Result<Json<Volume>, JsonRejection>,
+) -> (StatusCode, String) {
+    let limiter = state.limiter.lock().unwrap();
+    let success = limiter.try_acquire(1);
+    if !success {
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            "No milk available\n".to_string(),
+        );
+    }
+    let content_type_header = headers.get(CONTENT_TYPE);
+    let is_json = content_type_header == Some(&HeaderValue::from_static("application/json"));
+    if is_json {
+        let Json(volume) = match volume {
+            Ok(v) => v,
+            Err(_) => return (StatusCode::BAD_REQUEST, String::new()),
+        };
+        // JSONに変換
+        let json_value = serde_json::to_value(convert_volume(volume)).unwrap();
+        (StatusCode::OK, json_value.to_string())
+    } else {
+        (StatusCode::OK, "Milk withdrawn\n".to_string())
+    }
+}
+
+// ミルクのバケットを消費せずに単位変換だけを行う
+#[utoipa::path(
+    post,
+    path = "/9/convert",
+    tag = "milk",
+    request_body = Volume,
+    responses(
+        (status = 200, description = "The converted volume as JSON", body = Volume, content_type = "text/plain"),
+        (status = 400, description = "Invalid volume"),
+    ),
+)]
+async fn convert_milk(volume: Result<Json<Volume>, JsonRejection>) -> (StatusCode, String) {
+    let Ok(Json(volume)) = volume else {
+        return (StatusCode::BAD_REQUEST, String::new());
+    };
+    let json_value = serde_json::to_value(convert_volume(volume)).unwrap();
+    (StatusCode::OK, json_value.to_string())
+}
+
+#[utoipa::path(
+    post,
+    path = "/9/refill",
+    tag = "milk",
+    responses((status = 200, description = "The bucket is full again")),
+)]
+async fn refill_milk(State(state): State<AppState>) -> (StatusCode, String) {
+    let mut limiter = state.limiter.lock().unwrap();
+    *limiter = RateLimiter::builder()
+        .initial(BUCKET_SIZE)
+        .max(BUCKET_SIZE)
+        .interval(Duration::from_secs(REFILL_INTERVAL))
+        .build();
+    (StatusCode::OK, String::new())
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(withdraw_milk, refill_milk, convert_milk, list_volume_units,))]
+pub(crate) struct ApiDoc;
+
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/9/milk", post(withdraw_milk))
+        .route("/9/refill", post(refill_milk))
+        .route("/9/convert", post(convert_milk))
+        .route("/9/units", get(list_volume_units))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert_volume, Volume};
+    use crate::{testing::*, AppConfig};
+    use axum::http::StatusCode;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn milk_rate_limit_is_not_a_timeout() {
+        let config = AppConfig {
+            request_timeout: Duration::from_secs(1),
+            ..AppConfig::default()
+        };
+        let router = router_with(lazy_pool(), config);
+        let mut statuses = Vec::new();
+        for _ in 0..6 {
+            statuses.push(
+                send(&router, post("/9/milk", "text/plain", ""))
+                    .await
+                    .status,
+            );
+        }
+        assert!(statuses[..5].iter().all(|status| *status == StatusCode::OK));
+        assert_eq!(statuses[5], StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    fn assert_converts(volume: Volume, expected: serde_json::Value) {
+        let converted = serde_json::to_value(convert_volume(volume)).unwrap();
+        let (unit, value) = converted.as_object().unwrap().iter().next().unwrap();
+        let (expected_unit, expected_value) = expected.as_object().unwrap().iter().next().unwrap();
+        assert_eq!(unit, expected_unit);
+        let (value, expected_value) = (value.as_f64().unwrap(), expected_value.as_f64().unwrap());
+        assert!(
+            (value - expected_value).abs() < 1e-4,
+            "{} {}",
+            value,
+            expected_value
+        );
+    }
+
+    #[test]
+    fn convert_volume_each_direction() {
+        assert_converts(
+            Volume::Gallons(1.0),
+            serde_json::json!({ "liters": 3.785_411_8 }),
+        );
+        assert_converts(
+            Volume::Liters(3.785_411_8),
+            serde_json::json!({ "gallons": 1.0 }),
+        );
+        assert_converts(
+            Volume::Pints(2.0),
+            serde_json::json!({ "litres": 1.136_522_5 }),
+        );
+        assert_converts(
+            Volume::Litres(0.568_261_25),
+            serde_json::json!({ "pints": 1.0 }),
+        );
+    }
+
+    #[tokio::test]
+    async fn convert_does_not_use_the_milk_bucket() {
+        let router = router(lazy_pool());
+        let body = serde_json::json!({ "gallons": 1.0 });
+        for _ in 0..10 {
+            let response = send(&router, post_json("/9/convert", body.clone())).await;
+            assert_eq!(response.status, StatusCode::OK);
+            assert!(response.json()["liters"].is_number());
+        }
+        let response = send(&router, post("/9/milk", "text/plain", "")).await;
+        assert_eq!(response.status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn units_route_lists_every_unit() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/9/units")).await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        let units = response.json();
+        let units = units.as_array().unwrap();
+        assert_eq!(units.len(), 4);
+        let gallons = units.iter().find(|u| u["unit"] == "gallons").unwrap();
+        assert_eq!(gallons["target"], "liters");
+        assert!((gallons["factor"].as_f64().unwrap() - 3.785_411_8).abs() < 1e-4);
+    }
+}
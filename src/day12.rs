@@ -0,0 +1,940 @@
+use crate::{wants_json, AppState};
+use axum::{
+    extract::{Json, Path, Query, State},
+    http::{header::HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use shuttle_runtime::SecretStore;
+use std::fmt::Display;
+use utoipa::{OpenApi, ToSchema};
+
+#[derive(Clone, Copy, Deserialize, Serialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum Team {
+    Cookie,
+    Milk,
+}
+
+impl Team {
+    // パスのチーム名は大文字小文字を区別しない
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "cookie" => Some(Team::Cookie),
+            "milk" => Some(Team::Milk),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Board {
+    board: [[Option<Team>; 4]; 4],
+}
+
+impl Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = String::new();
+        for i in 0..4 {
+            output.push('⬜');
+            for j in 0..4 {
+                match self.board[j][i] {
+                    Some(Team::Cookie) => output.push('🍪'),
+                    Some(Team::Milk) => output.push('🥛'),
+                    None => output.push('⬛'),
+                }
+            }
+            output.push_str("⬜\n");
+        }
+        output.push_str("⬜⬜⬜⬜⬜⬜\n");
+        write!(f, "{}", output)
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BoardStyle {
+    #[default]
+    Emoji,
+    Ascii,
+}
+
+// 勝敗のメッセージのテンプレート（{team}がチームの記号に置き換わる）
+pub struct WinMessages {
+    cookie: String,
+    milk: String,
+    draw: String,
+}
+
+impl Default for WinMessages {
+    fn default() -> Self {
+        Self {
+            cookie: "{team} wins!".to_string(),
+            milk: "{team} wins!".to_string(),
+            draw: "No winner.".to_string(),
+        }
+    }
+}
+
+impl WinMessages {
+    pub fn from_secrets(secrets: &SecretStore) -> Result<Self, String> {
+        let defaults = WinMessages::default();
+        let messages = WinMessages {
+            cookie: secrets.get("WIN_MESSAGE_COOKIE").unwrap_or(defaults.cookie),
+            milk: secrets.get("WIN_MESSAGE_MILK").unwrap_or(defaults.milk),
+            draw: secrets.get("DRAW_MESSAGE").unwrap_or(defaults.draw),
+        };
+        for (name, template) in [
+            ("WIN_MESSAGE_COOKIE", &messages.cookie),
+            ("WIN_MESSAGE_MILK", &messages.milk),
+            ("DRAW_MESSAGE", &messages.draw),
+        ] {
+            if template.trim().is_empty() {
+                return Err(format!("{} must not be empty", name));
+            }
+            // {team}以外のプレースホルダーは書き間違いとみなす
+            if template.replace("{team}", "").contains(['{', '}']) {
+                return Err(format!(
+                    "{} may only contain the {{team}} placeholder",
+                    name
+                ));
+            }
+        }
+        Ok(messages)
+    }
+
+    fn win(&self, winner: Team, symbol: &str) -> String {
+        match winner {
+            Team::Cookie => &self.cookie,
+            Team::Milk => &self.milk,
+        }
+        .replace("{team}", symbol)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum BoardStatus {
+    Playing,
+    Won { winner: Team },
+    Draw,
+}
+
+impl Board {
+    // 絵文字が表示できない端末やログ向けのASCII表示
+    fn render_ascii(&self) -> String {
+        let mut output = String::new();
+        for i in 0..4 {
+            output.push('|');
+            for j in 0..4 {
+                match self.board[j][i] {
+                    Some(Team::Cookie) => output.push('C'),
+                    Some(Team::Milk) => output.push('M'),
+                    None => output.push('.'),
+                }
+            }
+            output.push_str("|\n");
+        }
+        output.push_str("------\n");
+        output
+    }
+
+    fn render(&self, style: BoardStyle) -> String {
+        match style {
+            BoardStyle::Emoji => self.to_string(),
+            BoardStyle::Ascii => self.render_ascii(),
+        }
+    }
+
+    fn check_winner(&self) -> Option<Team> {
+        // 縦横のチェック
+        for i in 0..4 {
+            // 横のチェック
+            if let Some(team) = self.board[i][0] {
+                if self.board[i][1] == Some(team)
+                    && self.board[i][2] == Some(team)
+                    && self.board[i][3] == Some(team)
+                {
+                    return Some(team);
+                }
+            }
+            // 縦のチェック
+            if let Some(team) = self.board[0][i] {
+                if self.board[1][i] == Some(team)
+                    && self.board[2][i] == Some(team)
+                    && self.board[3][i] == Some(team)
+                {
+                    return Some(team);
+                }
+            }
+        }
+
+        // 斜めのチェック（左上から右下）
+        if let Some(team) = self.board[0][0] {
+            if self.board[1][1] == Some(team)
+                && self.board[2][2] == Some(team)
+                && self.board[3][3] == Some(team)
+            {
+                return Some(team);
+            }
+        }
+
+        // 斜めのチェック（右上から左下）
+        if let Some(team) = self.board[0][3] {
+            if self.board[1][2] == Some(team)
+                && self.board[2][1] == Some(team)
+                && self.board[3][0] == Some(team)
+            {
+                return Some(team);
+            }
+        }
+
+        None
+    }
+
+    fn is_draw(&self) -> bool {
+        // すべてのマスが埋まっているかチェック
+        for row in self.board.iter() {
+            for cell in row.iter() {
+                if cell.is_none() {
+                    return false;
+                }
+            }
+        }
+        // 勝者がいない場合は引き分け
+        self.check_winner().is_none()
+    }
+
+    // 0始まりの列にピースを落とし、置けた行を返す（列が埋まっていればNone）
+    fn drop_piece(&mut self, team: Team, column: usize) -> Option<usize> {
+        // 下から順に空いている場所を探す
+        let row = (0..4)
+            .rev()
+            .find(|&row| self.board[column][row].is_none())?;
+        self.board[column][row] = Some(team);
+        Some(row)
+    }
+
+    fn status(&self) -> BoardStatus {
+        if let Some(winner) = self.check_winner() {
+            BoardStatus::Won { winner }
+        } else if self.is_draw() {
+            BoardStatus::Draw
+        } else {
+            BoardStatus::Playing
+        }
+    }
+
+    // JSONの行は上から順に並んでいるので、列ごとの内部表現に並べ替える
+    fn from_rows(rows: &[Vec<Option<Team>>]) -> Option<Self> {
+        if rows.len() != 4 || rows.iter().any(|row| row.len() != 4) {
+            return None;
+        }
+        let mut board = Board::default();
+        for (i, row) in rows.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                board.board[j][i] = *cell;
+            }
+        }
+        Some(board)
+    }
+
+    fn show_result(&self, messages: &WinMessages) -> Option<String> {
+        self.show_result_styled(BoardStyle::Emoji, messages)
+    }
+
+    fn show_result_styled(&self, style: BoardStyle, messages: &WinMessages) -> Option<String> {
+        let mut result = self.render(style);
+        result.push_str(&self.result_message(style, messages)?);
+        result.push('\n');
+        Some(result)
+    }
+
+    // 勝敗がついていれば結果のメッセージを返す（改行なし）
+    fn result_message(&self, style: BoardStyle, messages: &WinMessages) -> Option<String> {
+        if let Some(winner) = self.check_winner() {
+            let symbol = match (style, winner) {
+                (BoardStyle::Emoji, Team::Cookie) => "🍪",
+                (BoardStyle::Emoji, Team::Milk) => "🥛",
+                (BoardStyle::Ascii, Team::Cookie) => "C",
+                (BoardStyle::Ascii, Team::Milk) => "M",
+            };
+            Some(messages.win(winner, symbol))
+        } else if self.is_draw() {
+            Some(messages.draw.clone())
+        } else {
+            None
+        }
+    }
+
+    fn generate_random(rng: &mut rand::rngs::StdRng) -> Self {
+        let mut board = Board::default();
+        for i in 0..4 {
+            for j in 0..4 {
+                board.board[j][i] = Some(if rng.gen::<bool>() {
+                    Team::Cookie
+                } else {
+                    Team::Milk
+                });
+            }
+        }
+        board
+    }
+}
+
+#[derive(Deserialize)]
+struct BoardQuery {
+    #[serde(default)]
+    style: BoardStyle,
+    #[serde(default)]
+    oneline: bool,
+}
+
+// 複数行を扱えないチャットなど向けに、行を" / "でつなげて1行にする
+fn to_oneline(text: &str) -> String {
+    text.lines().collect::<Vec<_>>().join(" / ")
+}
+
+#[utoipa::path(
+    get,
+    path = "/12/board",
+    tag = "board",
+    params(
+        ("style" = Option<String>, Query, description = "emoji (default) or ascii"),
+        ("oneline" = Option<bool>, Query, description = "Join rows with \" / \""),
+    ),
+    responses((status = 200, description = "The board drawn with emoji", body = String, content_type = "text/plain; charset=utf-8")),
+)]
+async fn get_board(
+    State(state): State<AppState>,
+    Query(query): Query<BoardQuery>,
+) -> (StatusCode, String) {
+    let board = state.board.lock().unwrap();
+    let output = board
+        .show_result_styled(query.style, &state.win_messages)
+        .unwrap_or_else(|| board.render(query.style));
+    if query.oneline {
+        (StatusCode::OK, to_oneline(&output))
+    } else {
+        (StatusCode::OK, output)
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/12/reset",
+    tag = "board",
+    responses((status = 200, description = "The empty board", body = String, content_type = "text/plain")),
+)]
+async fn reset_board(State(state): State<AppState>) -> (StatusCode, String) {
+    let mut board = state.board.lock().unwrap();
+    *board = Board::default();
+    let mut rng = state.rng.lock().unwrap();
+    *rng = rand::rngs::StdRng::seed_from_u64(2024);
+    (StatusCode::OK, format!("{}", board))
+}
+
+#[derive(Serialize)]
+struct PlacementResult {
+    column: usize,
+    row: usize,
+    #[serde(flatten)]
+    status: BoardStatus,
+}
+
+#[tracing::instrument(skip_all, fields(column = column))]
+fn place_on_board(state: &AppState, team: Team, column: usize, json: bool) -> Response {
+    if !(1..=4).contains(&column) {
+        return (StatusCode::BAD_REQUEST, "Invalid column".to_string()).into_response();
+    }
+    let mut board = state.board.lock().unwrap();
+    let result = board.show_result(&state.win_messages);
+    if let Some(result) = result {
+        return (StatusCode::SERVICE_UNAVAILABLE, result).into_response();
+    }
+
+    let column = column - 1;
+    let Some(row) = board.drop_piece(team, column) else {
+        return (StatusCode::SERVICE_UNAVAILABLE, format!("{}", board)).into_response();
+    };
+    // 列・行とも1始まりで、行は盤面の表示と同じく上から数える
+    if json {
+        return Json(PlacementResult {
+            column: column + 1,
+            row: row + 1,
+            status: board.status(),
+        })
+        .into_response();
+    }
+    let result = board.show_result(&state.win_messages);
+    if let Some(result) = result {
+        return (StatusCode::OK, result).into_response();
+    }
+    (StatusCode::OK, format!("{}", board)).into_response()
+}
+
+#[derive(Serialize)]
+struct MovesResult {
+    board: String,
+    #[serde(flatten)]
+    status: BoardStatus,
+    // 適用できた手の数
+    applied: usize,
+    // 勝敗がついた手、または列が埋まっていて置けなかった手の番号（0始まり）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ending_move: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocked_move: Option<usize>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/12/moves",
+    tag = "board",
+    params(("index" = Option<u8>, Query, description = "Column base: 0 or 1 (default)")),
+    request_body = Vec<Placement>,
+    responses(
+        (status = 200, description = "Final board and how the replay ended", content_type = "application/json"),
+        (status = 400, description = "Invalid column", body = String, content_type = "text/plain"),
+        (status = 503, description = "Game already over", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn replay_moves(
+    State(state): State<AppState>,
+    Query(query): Query<PlaceQuery>,
+    Json(moves): Json<Vec<Placement>>,
+) -> Result<Json<MovesResult>, (StatusCode, String)> {
+    // 途中まで適用してから失敗しないよう、先に全部の列を確認する
+    let columns = moves
+        .iter()
+        .map(|placement| {
+            to_one_based_column(placement.column, query.index).and_then(|column| {
+                if (1..=4).contains(&column) {
+                    Ok(column - 1)
+                } else {
+                    Err((StatusCode::BAD_REQUEST, "Invalid column".to_string()))
+                }
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut board = state.board.lock().unwrap();
+    if let Some(result) = board.show_result(&state.win_messages) {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, result));
+    }
+
+    let mut applied = 0;
+    let mut ending_move = None;
+    let mut blocked_move = None;
+    for (i, (placement, column)) in moves.iter().zip(columns).enumerate() {
+        if board.drop_piece(placement.team, column).is_none() {
+            blocked_move = Some(i);
+            break;
+        }
+        applied += 1;
+        if !matches!(board.status(), BoardStatus::Playing) {
+            ending_move = Some(i);
+            break;
+        }
+    }
+
+    Ok(Json(MovesResult {
+        board: board.to_string(),
+        status: board.status(),
+        applied,
+        ending_move,
+        blocked_move,
+    }))
+}
+
+#[derive(Deserialize)]
+struct PlaceQuery {
+    index: Option<u8>,
+}
+
+// 0始まりの列指定を内部の1始まりに変換する
+fn to_one_based_column(column: usize, index: Option<u8>) -> Result<usize, (StatusCode, String)> {
+    match index.unwrap_or(1) {
+        0 if column <= 3 => Ok(column + 1),
+        0 => Err((StatusCode::BAD_REQUEST, "Invalid column".to_string())),
+        1 => Ok(column),
+        _ => Err((StatusCode::BAD_REQUEST, "Invalid index".to_string())),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/12/place/{team}/{column}",
+    tag = "board",
+    params(
+        ("team" = Team, Path, description = "cookie or milk, case-insensitive"),
+        ("column" = usize, Path, description = "Board column"),
+        ("index" = Option<u8>, Query, description = "Column base: 0 or 1 (default)"),
+    ),
+    responses(
+        (status = 200, description = "The board with status line (PlacementResult as JSON with Accept: application/json)", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid team or column", body = String, content_type = "text/plain"),
+        (status = 503, description = "Column full or game over", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn place_piece(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((team, column)): Path<(String, usize)>,
+    Query(query): Query<PlaceQuery>,
+) -> Response {
+    let Some(team) = Team::from_name(&team) else {
+        return (StatusCode::BAD_REQUEST, "Invalid team".to_string()).into_response();
+    };
+    match to_one_based_column(column, query.index) {
+        Ok(column) => place_on_board(&state, team, column, wants_json(&headers)),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct Placement {
+    team: Team,
+    column: usize,
+}
+
+#[utoipa::path(
+    post,
+    path = "/12/place",
+    tag = "board",
+    params(("index" = Option<u8>, Query, description = "Column base: 0 or 1 (default)")),
+    request_body = Placement,
+    responses(
+        (status = 200, description = "The board with status line (PlacementResult as JSON with Accept: application/json)", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid column", body = String, content_type = "text/plain"),
+        (status = 503, description = "Column full or game over", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn place_piece_json(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<PlaceQuery>,
+    Json(placement): Json<Placement>,
+) -> Response {
+    match to_one_based_column(placement.column, query.index) {
+        Ok(column) => place_on_board(&state, placement.team, column, wants_json(&headers)),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/12/check",
+    tag = "board",
+    request_body = Vec<Vec<Option<Team>>>,
+    responses(
+        (status = 200, description = "Winner or draw status", content_type = "application/json"),
+        (status = 400, description = "Board is not 4x4", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn check_board(
+    Json(rows): Json<Vec<Vec<Option<Team>>>>,
+) -> Result<Json<BoardStatus>, (StatusCode, String)> {
+    let board = Board::from_rows(&rows).ok_or((
+        StatusCode::BAD_REQUEST,
+        "Board must be 4 rows of 4 cells".to_string(),
+    ))?;
+    Ok(Json(board.status()))
+}
+
+fn render_random_board(board: &Board, messages: &WinMessages) -> String {
+    let result = board.to_string();
+    match board.result_message(BoardStyle::Emoji, messages) {
+        Some(message) => format!("{}{}", result, message),
+        None => result,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/12/random-board",
+    tag = "board",
+    responses((status = 200, description = "A seeded random board", body = String, content_type = "text/plain")),
+)]
+async fn random_board(State(state): State<AppState>) -> (StatusCode, String) {
+    let mut rng = state.rng.lock().unwrap();
+    let board = Board::generate_random(&mut rng);
+    (
+        StatusCode::OK,
+        render_random_board(&board, &state.win_messages),
+    )
+}
+
+#[derive(Deserialize)]
+struct RandomBatchQuery {
+    count: Option<usize>,
+    seed: Option<u64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/12/random-board/batch",
+    tag = "board",
+    params(
+        ("count" = Option<usize>, Query, description = "Number of boards"),
+        ("seed" = Option<u64>, Query, description = "Seed for reproducible boards"),
+    ),
+    responses((status = 200, description = "Rendered boards", body = Vec<String>)),
+)]
+async fn random_board_batch(
+    State(state): State<AppState>,
+    Query(query): Query<RandomBatchQuery>,
+) -> Json<Vec<String>> {
+    let count = query.count.unwrap_or(1).clamp(1, 100);
+    // ローカルのRNGで生成する（seed指定時は再現可能）
+    // 未指定でも共有RNGには触らない（/12/random-boardの並びが変わってしまうため）
+    let seed = query
+        .seed
+        .unwrap_or_else(|| rand::thread_rng().gen::<u64>());
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let boards = (0..count)
+        .map(|_| render_random_board(&Board::generate_random(&mut rng), &state.win_messages))
+        .collect();
+    Json(boards)
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_board,
+    reset_board,
+    place_piece_json,
+    check_board,
+    replay_moves,
+    place_piece,
+    random_board,
+    random_board_batch,
+))]
+pub(crate) struct ApiDoc;
+
+pub(crate) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/12/board", get(get_board))
+        .route("/12/reset", post(reset_board))
+        .route("/12/place", post(place_piece_json))
+        .route("/12/check", post(check_board))
+        .route("/12/moves", post(replay_moves))
+        .route("/12/place/:team/:column", post(place_piece))
+        .route("/12/random-board", get(random_board))
+        .route("/12/random-board/batch", get(random_board_batch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Team, WinMessages};
+    use crate::{testing::*, AppConfig};
+    use axum::{http::StatusCode, Router};
+
+    #[tokio::test]
+    async fn json_placement_uses_lowercase_team_names() {
+        let router = router(lazy_pool());
+        let response = send(
+            &router,
+            post_json(
+                "/12/place",
+                serde_json::json!({ "team": "milk", "column": 2 }),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(
+            response.text().ends_with("⬜⬛🥛⬛⬛⬜\n⬜⬜⬜⬜⬜⬜\n"),
+            "{}",
+            response.text()
+        );
+
+        let response = send(
+            &router,
+            post_json(
+                "/12/place",
+                serde_json::json!({ "team": "tea", "column": 2 }),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        assert_eq!(serde_json::to_value(Team::Cookie).unwrap(), "cookie");
+        assert_eq!(serde_json::to_value(Team::Milk).unwrap(), "milk");
+    }
+
+    async fn place(router: &Router, uri: &str) -> TestResponse {
+        send(router, post(uri, "text/plain", "")).await
+    }
+
+    #[tokio::test]
+    async fn columns_are_one_based_unless_index_is_zero() {
+        let router = router(lazy_pool());
+        for uri in [
+            "/12/place/cookie/1",
+            "/12/place/cookie/4",
+            "/12/place/cookie/0?index=0",
+            "/12/place/cookie/3?index=0",
+        ] {
+            assert_eq!(place(&router, uri).await.status, StatusCode::OK, "{}", uri);
+        }
+        for uri in [
+            "/12/place/cookie/0",
+            "/12/place/cookie/5",
+            "/12/place/cookie/4?index=0",
+        ] {
+            let response = place(&router, uri).await;
+            assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert_eq!(response.text(), "Invalid column");
+        }
+        let response = place(&router, "/12/place/cookie/1?index=2").await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.text(), "Invalid index");
+
+        let response = send(&router, get("/12/board")).await;
+        assert!(
+            response
+                .text()
+                .ends_with("⬜🍪⬛⬛🍪⬜\n⬜🍪⬛⬛🍪⬜\n⬜⬜⬜⬜⬜⬜\n"),
+            "{}",
+            response.text()
+        );
+    }
+
+    #[tokio::test]
+    async fn seeded_batch_is_reproducible() {
+        let router = router(lazy_pool());
+        let first = send(&router, get("/12/random-board/batch?count=10&seed=2024")).await;
+        assert_eq!(first.status, StatusCode::OK);
+        let boards = first.json();
+        assert_eq!(boards.as_array().unwrap().len(), 10);
+        let again = send(&router, get("/12/random-board/batch?count=10&seed=2024")).await;
+        assert_eq!(again.json(), boards);
+
+        // 共有RNGと同じシードなら/12/random-boardと同じ並びになる
+        for board in boards.as_array().unwrap() {
+            assert_eq!(
+                send(&router, get("/12/random-board")).await.text(),
+                board.as_str().unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn unseeded_batch_keeps_random_board_sequence() {
+        let expected = router(lazy_pool());
+        send(&expected, get("/12/random-board")).await;
+        let second = send(&expected, get("/12/random-board")).await.text();
+
+        let router = router(lazy_pool());
+        send(&router, get("/12/random-board")).await;
+        let response = send(&router, get("/12/random-board/batch?count=3")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(send(&router, get("/12/random-board")).await.text(), second);
+    }
+
+    #[tokio::test]
+    async fn board_can_be_drawn_in_ascii() {
+        let router = router(lazy_pool());
+        place(&router, "/12/place/cookie/1").await;
+        place(&router, "/12/place/milk/2").await;
+        let response = send(&router, get("/12/board?style=ascii")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "|....|\n|....|\n|....|\n|CM..|\n------\n");
+
+        for _ in 0..3 {
+            place(&router, "/12/place/cookie/1").await;
+        }
+        let response = send(&router, get("/12/board?style=ascii")).await;
+        assert!(
+            response.text().ends_with("------\nC wins!\n"),
+            "{}",
+            response.text()
+        );
+        let response = send(&router, get("/12/board")).await;
+        assert!(
+            response.text().ends_with("🍪 wins!\n"),
+            "{}",
+            response.text()
+        );
+    }
+
+    #[tokio::test]
+    async fn check_reports_the_board_status() {
+        let router = router(lazy_pool());
+        let diagonal = serde_json::json!([
+            ["milk", null, null, "cookie"],
+            [null, null, "cookie", null],
+            [null, "cookie", "milk", null],
+            ["cookie", "milk", "milk", "milk"],
+        ]);
+        let response = send(&router, post_json("/12/check", diagonal)).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json(),
+            serde_json::json!({ "status": "won", "winner": "cookie" })
+        );
+
+        let draw = serde_json::json!([
+            ["cookie", "cookie", "milk", "milk"],
+            ["milk", "milk", "cookie", "cookie"],
+            ["cookie", "cookie", "milk", "milk"],
+            ["milk", "milk", "cookie", "cookie"],
+        ]);
+        let response = send(&router, post_json("/12/check", draw)).await;
+        assert_eq!(response.json(), serde_json::json!({ "status": "draw" }));
+
+        let playing = serde_json::json!([
+            [null, null, null, null],
+            [null, null, null, null],
+            [null, null, null, null],
+            ["cookie", "milk", null, null],
+        ]);
+        let response = send(&router, post_json("/12/check", playing)).await;
+        assert_eq!(response.json(), serde_json::json!({ "status": "playing" }));
+
+        let response = send(&router, post_json("/12/check", serde_json::json!([[null]]))).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn win_message_comes_from_secrets() {
+        let messages = WinMessages::from_secrets(&secrets(&[(
+            "WIN_MESSAGE_COOKIE",
+            "{team} takes the milk!",
+        )]))
+        .unwrap();
+        let config = AppConfig {
+            win_messages: messages,
+            ..AppConfig::default()
+        };
+        let router = router_with(lazy_pool(), config);
+        for _ in 0..4 {
+            place(&router, "/12/place/cookie/1").await;
+        }
+        let response = send(&router, get("/12/board")).await;
+        assert!(
+            response
+                .text()
+                .ends_with("⬜⬜⬜⬜⬜⬜\n🍪 takes the milk!\n"),
+            "{}",
+            response.text()
+        );
+
+        let error = WinMessages::from_secrets(&secrets(&[("DRAW_MESSAGE", "{winner}")])).err();
+        assert_eq!(
+            error.as_deref(),
+            Some("DRAW_MESSAGE may only contain the {team} placeholder")
+        );
+    }
+
+    #[tokio::test]
+    async fn json_placement_reports_where_the_piece_landed() {
+        let router = router(lazy_pool());
+        let mut responses = Vec::new();
+        for _ in 0..2 {
+            let mut request = post("/12/place/cookie/2", "text/plain", "");
+            request
+                .headers_mut()
+                .insert("accept", "application/json".parse().unwrap());
+            responses.push(send(&router, request).await.json());
+        }
+        assert_eq!(
+            responses[0],
+            serde_json::json!({ "column": 2, "row": 4, "status": "playing" })
+        );
+        assert_eq!(
+            responses[1],
+            serde_json::json!({ "column": 2, "row": 3, "status": "playing" })
+        );
+    }
+
+    #[tokio::test]
+    async fn board_can_be_drawn_on_one_line() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/12/board?oneline=true&style=ascii")).await;
+        assert_eq!(
+            response.text(),
+            "|....| / |....| / |....| / |....| / ------"
+        );
+    }
+
+    #[tokio::test]
+    async fn replay_stops_at_the_winning_move() {
+        let router = router(lazy_pool());
+        let moves: Vec<_> = (0..6)
+            .map(|_| serde_json::json!({ "team": "milk", "column": 3 }))
+            .collect();
+        let response = send(&router, post_json("/12/moves", serde_json::json!(moves))).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let result = response.json();
+        assert_eq!(result["status"], "won");
+        assert_eq!(result["winner"], "milk");
+        assert_eq!(result["applied"], 4);
+        assert_eq!(result["ending_move"], 3);
+        assert!(result.get("blocked_move").is_none());
+
+        // 決着がついた後は何も置けない
+        let response = send(
+            &router,
+            post_json(
+                "/12/moves",
+                serde_json::json!([{ "team": "cookie", "column": 1 }]),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn replay_reports_a_blocked_move_and_checks_columns_first() {
+        let router = router(lazy_pool());
+        let response = send(
+            &router,
+            post_json(
+                "/12/moves",
+                serde_json::json!([
+                    { "team": "cookie", "column": 1 },
+                    { "team": "milk", "column": 5 },
+                ]),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            send(&router, get("/12/board")).await.text(),
+            "⬜⬛⬛⬛⬛⬜\n⬜⬛⬛⬛⬛⬜\n⬜⬛⬛⬛⬛⬜\n⬜⬛⬛⬛⬛⬜\n⬜⬜⬜⬜⬜⬜\n"
+        );
+
+        let moves: Vec<_> = ["cookie", "milk", "cookie", "milk", "cookie"]
+            .iter()
+            .map(|team| serde_json::json!({ "team": team, "column": 2 }))
+            .collect();
+        let response = send(&router, post_json("/12/moves", serde_json::json!(moves))).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let result = response.json();
+        assert_eq!(result["status"], "playing");
+        assert_eq!(result["applied"], 4);
+        assert_eq!(result["blocked_move"], 4);
+        assert!(result.get("ending_move").is_none());
+    }
+
+    #[tokio::test]
+    async fn team_names_are_case_insensitive() {
+        let router = router(lazy_pool());
+        assert_eq!(
+            place(&router, "/12/place/COOKIE/1").await.status,
+            StatusCode::OK
+        );
+        assert_eq!(
+            place(&router, "/12/place/Milk/2").await.status,
+            StatusCode::OK
+        );
+        let response = place(&router, "/12/place/cake/3").await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.text(), "Invalid team");
+        let board = send(&router, get("/12/board?style=ascii")).await.text();
+        assert!(board.ends_with("|CM..|\n------\n"), "{}", board);
+    }
+}
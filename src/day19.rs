@@ -0,0 +1,3727 @@
+use crate::{
+    error::{ApiError, ApiErrorResponse, AppError},
+    repository, AppState,
+};
+use axum::{
+    body::Body,
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        ConnectInfo, Json, Path, Query, Request, State,
+    },
+    http::{
+        header::{self, HeaderMap, CONTENT_TYPE},
+        HeaderValue, StatusCode,
+    },
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use leaky_bucket::RateLimiter;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tracing::{instrument, Span};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+pub(crate) const DEFAULT_WRITE_BUCKET_SIZE: usize = 10;
+pub(crate) const DEFAULT_WRITE_REFILL_INTERVAL: u64 = 1;
+const MAX_WRITE_BUCKETS: usize = 10_000;
+
+const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const IDEMPOTENCY_SWEEP_INTERVAL: u64 = 60 * 60;
+
+pub(crate) const DEFAULT_TOKEN_TTL_SECS: u64 = 15 * 60;
+pub(crate) const DEFAULT_MAX_TOKENS: i64 = 10_000;
+const TOKEN_SWEEP_INTERVAL: u64 = 60;
+
+pub(crate) const DEFAULT_MAX_AUTHOR_CHARS: usize = 256;
+pub(crate) const DEFAULT_MAX_QUOTE_CHARS: usize = 4096;
+
+#[derive(sqlx::FromRow, Serialize, ToSchema)]
+pub(crate) struct Quote {
+    id: Uuid,
+    author: String,
+    quote: String,
+    #[serde(serialize_with = "serialize_millis")]
+    created_at: DateTime<Utc>,
+    version: i32,
+}
+
+// DBの精度に関係なく、常にミリ秒までのRFC3339（UTC, Z付き）で出力する
+fn format_millis(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    timestamp: &DateTime<Utc>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format_millis(timestamp))
+}
+
+// 未来の時刻は時計のずれを考慮して1分まで許容する
+const MAX_CREATED_AT_SKEW_SECS: i64 = 60;
+
+#[derive(Deserialize, ToSchema)]
+struct Draft {
+    author: String,
+    quote: String,
+    // 過去の引用をインポートするときだけ指定する
+    created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Copy)]
+pub struct DraftLimits {
+    pub max_author_chars: usize,
+    pub max_quote_chars: usize,
+}
+
+#[derive(Serialize)]
+struct FieldError {
+    field: &'static str,
+    rule: &'static str,
+    message: String,
+}
+
+fn validate_field(field: &'static str, value: &str, max_chars: usize) -> Option<FieldError> {
+    // 文字数はバイト数ではなく文字単位で数える
+    if value.trim().is_empty() {
+        Some(FieldError {
+            field,
+            rule: "not_empty",
+            message: format!("{} must not be empty", field),
+        })
+    } else if value.chars().count() > max_chars {
+        Some(FieldError {
+            field,
+            rule: "max_length",
+            message: format!("{} must be at most {} characters", field, max_chars),
+        })
+    } else {
+        None
+    }
+}
+
+fn validate_created_at(created_at: DateTime<Utc>) -> Option<FieldError> {
+    if created_at < DateTime::UNIX_EPOCH {
+        Some(FieldError {
+            field: "created_at",
+            rule: "too_old",
+            message: "created_at must not be before 1970-01-01T00:00:00Z".to_string(),
+        })
+    } else if created_at > Utc::now() + chrono::Duration::seconds(MAX_CREATED_AT_SKEW_SECS) {
+        Some(FieldError {
+            field: "created_at",
+            rule: "in_future",
+            message: "created_at must not be in the future".to_string(),
+        })
+    } else {
+        None
+    }
+}
+
+impl Draft {
+    fn validate(&self, limits: &DraftLimits) -> Result<(), Vec<FieldError>> {
+        let errors = [
+            validate_field("author", &self.author, limits.max_author_chars),
+            validate_field("quote", &self.quote, limits.max_quote_chars),
+            self.created_at.and_then(validate_created_at),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DraftPatch {
+    author: Option<String>,
+    quote: Option<String>,
+}
+
+impl DraftPatch {
+    // 指定されたフィールドだけDraftと同じルールで検証する
+    fn validate(&self, limits: &DraftLimits) -> Result<(), Vec<FieldError>> {
+        let errors = [
+            self.author
+                .as_deref()
+                .and_then(|v| validate_field("author", v, limits.max_author_chars)),
+            self.quote
+                .as_deref()
+                .and_then(|v| validate_field("quote", v, limits.max_quote_chars)),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn validation_error(errors: Vec<FieldError>) -> ApiError {
+    ApiError::bad_request("Invalid draft")
+        .with_code("validation_failed")
+        .with_details(serde_json::to_value(errors).unwrap())
+}
+
+#[derive(Serialize, ToSchema)]
+struct QuoteList {
+    quotes: Vec<Quote>,
+    page: i32,
+    limit: i64,
+    next_token: Option<String>,
+    total: i64,
+    total_pages: i64,
+    has_prev: bool,
+    prev_token: Option<String>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum QuoteSort {
+    #[default]
+    CreatedAt,
+    Author,
+    Version,
+    // UUIDv7は生成順に並ぶので、主キーのインデックスだけで作成順に辿れる
+    Id,
+}
+
+impl QuoteSort {
+    fn name(self) -> &'static str {
+        match self {
+            QuoteSort::CreatedAt => "created_at",
+            QuoteSort::Author => "author",
+            QuoteSort::Version => "version",
+            QuoteSort::Id => "id",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "created_at" => Some(QuoteSort::CreatedAt),
+            "author" => Some(QuoteSort::Author),
+            "version" => Some(QuoteSort::Version),
+            "id" => Some(QuoteSort::Id),
+            _ => None,
+        }
+    }
+
+    // カーソルはテキストで保存するので、比較時にキャストする型
+    fn sql_type(self) -> &'static str {
+        match self {
+            QuoteSort::CreatedAt => "timestamptz",
+            QuoteSort::Author => "text",
+            QuoteSort::Version => "int",
+            QuoteSort::Id => "uuid",
+        }
+    }
+
+    fn cursor_value(self, quote: &Quote) -> String {
+        match self {
+            QuoteSort::CreatedAt => quote
+                .created_at
+                .to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            QuoteSort::Author => quote.author.clone(),
+            QuoteSort::Version => quote.version.to_string(),
+            QuoteSort::Id => quote.id.to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// トークンを発行したエンドポイント。別のエンドポイントではトークンを使えない
+#[derive(Clone, Copy, PartialEq)]
+enum TokenEndpoint {
+    List,
+    Search,
+    ByAuthor,
+    Fts,
+}
+
+impl TokenEndpoint {
+    fn name(self) -> &'static str {
+        match self {
+            TokenEndpoint::List => "list",
+            TokenEndpoint::Search => "search",
+            TokenEndpoint::ByAuthor => "by_author",
+            TokenEndpoint::Fts => "fts",
+        }
+    }
+}
+
+#[derive(Clone, sqlx::FromRow)]
+struct PaginationState {
+    // TokenEndpointの名前
+    endpoint: String,
+    page: i32,
+    per_page: i64,
+    // 並び順（QuoteSortの名前）
+    sort: String,
+    descending: bool,
+    // キーセットページネーションのカーソル（最初のページではNone）
+    cursor_value: Option<String>,
+    cursor_id: Option<Uuid>,
+    // trueならカーソルより前のページを返す
+    backward: bool,
+    // 検索条件（エスケープ済みのILIKEパターン）
+    author_pattern: Option<String>,
+    quote_pattern: Option<String>,
+    // 作者の完全一致（大文字小文字も区別する）
+    author: Option<String>,
+    // 全文検索のクエリ（websearch_to_tsqueryの入力）
+    fts_query: Option<String>,
+    // 作成日時の範囲（どちらも境界は含まない）
+    created_after: Option<DateTime<Utc>>,
+    created_before: Option<DateTime<Utc>>,
+}
+
+// ページネーショントークンはDBに保存し、再起動後も使えるようにする
+#[derive(Clone)]
+pub(crate) struct PaginationTokens {
+    ttl: Duration,
+    // この期間を過ぎたトークンは掃除で削除する（使えなくなるのはttlを過ぎた時点）
+    retention: Duration,
+    max_tokens: i64,
+    // テスト用：設定されていればランダムではなく連番のトークンを発行する
+    deterministic_counter: Option<Arc<AtomicU64>>,
+}
+
+impl PaginationTokens {
+    pub(crate) fn new(
+        ttl: Duration,
+        retention: Duration,
+        max_tokens: i64,
+        deterministic: bool,
+    ) -> Self {
+        Self {
+            ttl,
+            retention,
+            max_tokens,
+            deterministic_counter: deterministic.then(|| Arc::new(AtomicU64::new(0))),
+        }
+    }
+
+    async fn insert(
+        &self,
+        pool: &sqlx::PgPool,
+        token: &str,
+        state: &PaginationState,
+    ) -> Result<(), sqlx::Error> {
+        // 期限切れのトークンは発行のついでに削除する
+        self.sweep(pool).await?;
+        sqlx::query(
+            "INSERT INTO pagination_tokens \
+             (token, page, per_page, sort, descending, cursor_value, cursor_id, backward, \
+              author_pattern, quote_pattern, author, fts_query, created_after, created_before, \
+              endpoint) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)",
+        )
+        .bind(token)
+        .bind(state.page)
+        .bind(state.per_page)
+        .bind(&state.sort)
+        .bind(state.descending)
+        .bind(&state.cursor_value)
+        .bind(state.cursor_id)
+        .bind(state.backward)
+        .bind(&state.author_pattern)
+        .bind(&state.quote_pattern)
+        .bind(&state.author)
+        .bind(&state.fts_query)
+        .bind(state.created_after)
+        .bind(state.created_before)
+        .bind(&state.endpoint)
+        .execute(pool)
+        .await?;
+        // 上限を超えた分は古いものから削除する
+        sqlx::query(
+            "DELETE FROM pagination_tokens WHERE token IN \
+             (SELECT token FROM pagination_tokens ORDER BY created_at DESC OFFSET $1)",
+        )
+        .bind(self.max_tokens)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    // トークンは一度使ったら削除する
+    async fn take(
+        &self,
+        pool: &sqlx::PgPool,
+        token: &str,
+    ) -> Result<Option<PaginationState>, sqlx::Error> {
+        sqlx::query_as::<_, PaginationState>(
+            "DELETE FROM pagination_tokens \
+             WHERE token = $1 AND created_at > now() - make_interval(secs => $2) \
+             RETURNING page, per_page, sort, descending, cursor_value, cursor_id, backward, \
+             author_pattern, quote_pattern, author, fts_query, created_after, created_before, \
+             endpoint",
+        )
+        .bind(token)
+        .bind(self.ttl.as_secs_f64())
+        .fetch_optional(pool)
+        .await
+    }
+
+    async fn sweep(&self, pool: &sqlx::PgPool) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "DELETE FROM pagination_tokens WHERE created_at <= now() - make_interval(secs => $1)",
+        )
+        .bind(self.retention.as_secs_f64())
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub(crate) async fn len(&self, pool: &sqlx::PgPool) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM pagination_tokens")
+            .fetch_one(pool)
+            .await
+    }
+}
+
+struct WriteBucket {
+    limiter: RateLimiter,
+    last_used: Instant,
+}
+
+// クライアントごとに別のバケットを持つ書き込み用のレートリミッター
+#[derive(Clone)]
+pub(crate) struct WriteLimiter {
+    buckets: Arc<Mutex<HashMap<String, WriteBucket>>>,
+    bucket_size: usize,
+    interval: Duration,
+    max_buckets: usize,
+}
+
+impl WriteLimiter {
+    pub(crate) fn new(bucket_size: usize, interval: Duration) -> Self {
+        Self {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+            bucket_size,
+            interval,
+            max_buckets: MAX_WRITE_BUCKETS,
+        }
+    }
+
+    fn try_acquire(&self, client: &str) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        if !buckets.contains_key(client) && buckets.len() >= self.max_buckets {
+            // 満タンに戻ったバケットは新規作成と同じなので捨ててよい
+            buckets.retain(|_, bucket| bucket.limiter.balance() < self.bucket_size);
+            // それでも空かなければ、最も長く使われていないバケットを捨てる
+            if buckets.len() >= self.max_buckets {
+                let oldest = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.last_used)
+                    .map(|(client, _)| client.clone());
+                if let Some(oldest) = oldest {
+                    buckets.remove(&oldest);
+                }
+            }
+        }
+        let bucket = buckets
+            .entry(client.to_string())
+            .or_insert_with(|| WriteBucket {
+                limiter: RateLimiter::builder()
+                    .initial(self.bucket_size)
+                    .max(self.bucket_size)
+                    .interval(self.interval)
+                    .build(),
+                last_used: Instant::now(),
+            });
+        bucket.last_used = Instant::now();
+        bucket.limiter.try_acquire(1)
+    }
+
+    fn retry_after_secs(&self) -> u64 {
+        self.interval.as_secs_f64().ceil().max(1.0) as u64
+    }
+}
+
+const MAX_REVISIONS_PER_QUOTE: i64 = 50;
+
+#[derive(sqlx::FromRow, Serialize, ToSchema)]
+struct QuoteRevision {
+    quote_id: Uuid,
+    version: i32,
+    author: String,
+    quote: String,
+    recorded_at: DateTime<Utc>,
+}
+
+// 引用の現在の内容を履歴に記録し、上限を超えた古い履歴を削除する
+async fn record_revision(conn: &mut sqlx::PgConnection, quote: &Quote) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO quote_revisions (quote_id, version, author, quote) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(quote.id)
+    .bind(quote.version)
+    .bind(&quote.author)
+    .bind(&quote.quote)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        "DELETE FROM quote_revisions WHERE quote_id = $1 AND version NOT IN \
+         (SELECT version FROM quote_revisions WHERE quote_id = $1 ORDER BY version DESC LIMIT $2)",
+    )
+    .bind(quote.id)
+    .bind(MAX_REVISIONS_PER_QUOTE)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/history/{id}",
+    tag = "quotes",
+    params(("id" = Uuid, Path, description = "Quote ID")),
+    responses(
+        (status = 200, description = "Revisions, newest first", body = Vec<QuoteRevision>),
+        (status = 404, description = "Quote not found", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all, fields(quote_id))]
+async fn get_quote_history(
+    State(state): State<AppState>,
+    id: Result<Path<Uuid>, PathRejection>,
+) -> Result<Json<Vec<QuoteRevision>>, AppError> {
+    let Path(id) = id?;
+    Span::current().record("quote_id", tracing::field::display(id));
+    let revisions = repository::with_retry(|| {
+        sqlx::query_as::<_, QuoteRevision>(
+            "SELECT * FROM quote_revisions WHERE quote_id = $1 ORDER BY version DESC",
+        )
+        .bind(id)
+        .fetch_all(&state.pool)
+    })
+    .await?;
+    // 作成時に必ずversion 1が記録されるので、空なら存在しないIDとみなす
+    if revisions.is_empty() {
+        return Err(ApiError::not_found("Quote not found").into());
+    }
+    Ok(Json(revisions))
+}
+
+#[utoipa::path(
+    post,
+    path = "/19/reset",
+    tag = "quotes",
+    responses(
+        (status = 200, description = "All quotes deleted", body = String, content_type = "text/plain"),
+        (status = 429, description = "Too many writes from this client", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn reset_quotes(State(state): State<AppState>) -> Result<(StatusCode, String), AppError> {
+    let mut tx = state.pool.begin().await?;
+    repository::clear_quotes(&mut tx).await?;
+    tx.commit().await?;
+    Ok((StatusCode::OK, "Quotes reset".to_string()))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ConfirmQuery {
+    confirm: Option<bool>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct DeletedQuotes {
+    deleted: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+struct PurgedTokens {
+    purged: u64,
+}
+
+// 保持期間を過ぎたページネーショントークンをその場で掃除する（定期的な掃除と同じ処理）
+#[utoipa::path(
+    post,
+    path = "/19/gc",
+    tag = "quotes",
+    responses(
+        (status = 200, description = "Expired pagination tokens removed", body = PurgedTokens),
+        (status = 429, description = "Too many writes from this client", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn collect_pagination_tokens(
+    State(state): State<AppState>,
+) -> Result<Json<PurgedTokens>, AppError> {
+    let purged = state.pagination_tokens.sweep(&state.pool).await?;
+    Ok(Json(PurgedTokens { purged }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/19/quotes",
+    tag = "quotes",
+    params(
+        ConfirmQuery,
+        ("X-Confirm-Delete" = Option<bool>, Header, description = "Alternative to ?confirm=true"),
+    ),
+    responses(
+        (status = 200, description = "All quotes deleted", body = DeletedQuotes),
+        (status = 400, description = "Confirmation missing", body = ApiErrorResponse),
+        (status = 429, description = "Too many writes from this client", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn delete_all_quotes(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    query: Result<Query<ConfirmQuery>, QueryRejection>,
+) -> Result<Json<DeletedQuotes>, AppError> {
+    let Query(query) = query?;
+    // クエリかヘッダーで明示的に確認された場合のみ全削除する
+    let confirmed_by_header = headers
+        .get("X-Confirm-Delete")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("true"));
+    if query.confirm != Some(true) && !confirmed_by_header {
+        return Err(ApiError::bad_request(
+            "Confirmation required: pass ?confirm=true or X-Confirm-Delete: true",
+        )
+        .into());
+    }
+
+    let mut tx = state.pool.begin().await?;
+    let deleted = repository::clear_quotes(&mut tx).await?;
+    tx.commit().await?;
+    Ok(Json(DeletedQuotes { deleted }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/cite/{id}",
+    tag = "quotes",
+    params(("id" = Uuid, Path, description = "Quote ID")),
+    responses(
+        (status = 200, description = "The quote as JSON", body = Quote, content_type = "text/plain"),
+        (status = 404, description = "Quote not found", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all, fields(quote_id))]
+async fn get_quotes(
+    State(state): State<AppState>,
+    id: Result<Path<Uuid>, PathRejection>,
+) -> Result<(StatusCode, String), AppError> {
+    let Path(id) = id?;
+    Span::current().record("quote_id", tracing::field::display(id));
+    let quote = repository::with_retry(|| repository::find_quote(&state.pool, id)).await?;
+    if let Some(quote) = quote {
+        Ok((StatusCode::OK, serde_json::to_string(&quote).unwrap()))
+    } else {
+        Err(ApiError::not_found("Quote not found").into())
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/cite/{id}/raw",
+    tag = "quotes",
+    params(("id" = Uuid, Path, description = "Quote ID")),
+    responses(
+        (status = 200, description = "The quote text only", body = String, content_type = "text/plain"),
+        (status = 404, description = "Quote not found", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all, fields(quote_id))]
+async fn get_quote_raw(
+    State(state): State<AppState>,
+    id: Result<Path<Uuid>, PathRejection>,
+) -> Result<Response, AppError> {
+    let Path(id) = id?;
+    Span::current().record("quote_id", tracing::field::display(id));
+    let quote = repository::with_retry(|| repository::find_quote(&state.pool, id))
+        .await?
+        .ok_or(ApiError::not_found("Quote not found"))?;
+    Ok(([(CONTENT_TYPE, "text/plain; charset=utf-8")], quote.quote).into_response())
+}
+
+#[derive(Serialize, ToSchema)]
+struct QuoteTotal {
+    total: i64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/total",
+    tag = "quotes",
+    responses((status = 200, description = "Number of quotes", body = QuoteTotal)),
+)]
+#[instrument(skip_all)]
+async fn get_quote_total(State(state): State<AppState>) -> Result<Json<QuoteTotal>, AppError> {
+    let total = repository::with_retry(|| repository::count_quotes(&state.pool)).await?;
+    Ok(Json(QuoteTotal { total }))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StatsQuery {
+    min_count: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct QuoteStats {
+    authors: Vec<repository::AuthorStats>,
+    earliest: Option<DateTime<Utc>>,
+    latest: Option<DateTime<Utc>>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/stats",
+    tag = "quotes",
+    params(StatsQuery),
+    responses((status = 200, description = "Per-author statistics", body = QuoteStats)),
+)]
+#[instrument(skip_all)]
+async fn get_quote_stats(
+    State(state): State<AppState>,
+    query: Result<Query<StatsQuery>, QueryRejection>,
+) -> Result<Json<QuoteStats>, AppError> {
+    let Query(query) = query?;
+    let min_count = query.min_count.unwrap_or(1);
+    let authors =
+        repository::with_retry(|| repository::author_stats(&state.pool, min_count)).await?;
+    // 空の場合はauthorsが空配列、earliest/latestがnullになる
+    let earliest = authors.iter().map(|a| a.earliest).min();
+    let latest = authors.iter().map(|a| a.latest).max();
+    Ok(Json(QuoteStats {
+        authors,
+        earliest,
+        latest,
+    }))
+}
+
+const MAX_AUTHORS_PER_PAGE: i64 = 1000;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AuthorsQuery {
+    prefix: Option<String>,
+    after: Option<String>,
+    limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/authors",
+    tag = "quotes",
+    params(AuthorsQuery),
+    responses(
+        (status = 200, description = "Authors in ascending order", body = Vec<repository::AuthorSummary>),
+        (status = 400, description = "Invalid request", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn list_authors(
+    State(state): State<AppState>,
+    query: Result<Query<AuthorsQuery>, QueryRejection>,
+) -> Result<Json<Vec<repository::AuthorSummary>>, AppError> {
+    let Query(query) = query?;
+    if query
+        .limit
+        .is_some_and(|limit| !(1..=MAX_AUTHORS_PER_PAGE).contains(&limit))
+    {
+        return Err(ApiError::bad_request(format!(
+            "limit must be between 1 and {}",
+            MAX_AUTHORS_PER_PAGE
+        ))
+        .into());
+    }
+    // 前方一致は大文字小文字を無視し、ワイルドカード文字はエスケープする
+    let prefix_pattern = query
+        .prefix
+        .as_deref()
+        .map(|prefix| format!("{}%", escape_like(prefix)));
+    let authors = repository::with_retry(|| {
+        repository::list_authors(
+            &state.pool,
+            prefix_pattern.as_deref(),
+            query.after.as_deref(),
+            query.limit,
+        )
+    })
+    .await?;
+    Ok(Json(authors))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/19/cite/{id}",
+    tag = "quotes",
+    params(("id" = Uuid, Path, description = "Quote ID"), ("If-Match" = Option<String>, Header, description = "Expected quote version")),
+    request_body = DraftPatch,
+    responses(
+        (status = 200, description = "The quote as JSON", body = Quote, content_type = "text/plain"),
+        (status = 400, description = "Invalid request", body = ApiErrorResponse),
+        (status = 404, description = "Quote not found", body = ApiErrorResponse),
+        (status = 409, description = "If-Match version is stale", body = ApiErrorResponse),
+        (status = 429, description = "Too many writes from this client", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all, fields(quote_id))]
+async fn patch_quote(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    id: Result<Path<Uuid>, PathRejection>,
+    patch: Result<Json<DraftPatch>, JsonRejection>,
+) -> Result<(StatusCode, String), AppError> {
+    let Path(id) = id?;
+    Span::current().record("quote_id", tracing::field::display(id));
+    let Json(patch) = patch?;
+    let expected_version = if_match_version(&headers)?;
+    // 更新するカラムの組み合わせごとに固定のSQLを使う
+    let sql = match (&patch.author, &patch.quote) {
+        (Some(_), Some(_)) => {
+            "UPDATE quotes SET author = $2, quote = $3, version = version + 1 \
+             WHERE id = $1 AND ($4::int IS NULL OR version = $4) \
+             RETURNING id, author, quote, created_at, version"
+        }
+        (Some(_), None) => {
+            "UPDATE quotes SET author = $2, version = version + 1 \
+             WHERE id = $1 AND ($4::int IS NULL OR version = $4) \
+             RETURNING id, author, quote, created_at, version"
+        }
+        (None, Some(_)) => {
+            "UPDATE quotes SET quote = $3, version = version + 1 \
+             WHERE id = $1 AND ($4::int IS NULL OR version = $4) \
+             RETURNING id, author, quote, created_at, version"
+        }
+        (None, None) => {
+            return Err(ApiError::bad_request("Nothing to update").into());
+        }
+    };
+    patch
+        .validate(&state.draft_limits)
+        .map_err(validation_error)?;
+    let mut tx = state.pool.begin().await?;
+    let quote = sqlx::query_as::<_, Quote>(sql)
+        .bind(id)
+        .bind(&patch.author)
+        .bind(&patch.quote)
+        .bind(expected_version)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some(quote) = quote else {
+        return Err(update_miss(&mut tx, id, expected_version).await);
+    };
+    record_revision(&mut tx, &quote).await?;
+    tx.commit().await?;
+    Ok((StatusCode::OK, serde_json::to_string(&quote).unwrap()))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RemoveQuery {
+    keep_history: Option<bool>,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/19/remove/{id}",
+    tag = "quotes",
+    params(("id" = Uuid, Path, description = "Quote ID"), RemoveQuery),
+    responses(
+        (status = 200, description = "The removed quote as JSON", body = Quote, content_type = "text/plain"),
+        (status = 404, description = "Quote not found", body = ApiErrorResponse),
+        (status = 429, description = "Too many writes from this client", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all, fields(quote_id))]
+async fn remove_quotes(
+    State(state): State<AppState>,
+    id: Result<Path<Uuid>, PathRejection>,
+    query: Result<Query<RemoveQuery>, QueryRejection>,
+) -> Result<(StatusCode, String), AppError> {
+    let Path(id) = id?;
+    Span::current().record("quote_id", tracing::field::display(id));
+    let Query(query) = query?;
+    let mut tx = state.pool.begin().await?;
+    // 取得と削除を1文で行い、同時に削除された場合は片方だけが成功する
+    let quote = sqlx::query_as::<_, Quote>(
+        "DELETE FROM quotes WHERE id = $1 RETURNING id, author, quote, created_at, version",
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(quote) = quote {
+        // keep_history=trueなら履歴は残す
+        if query.keep_history != Some(true) {
+            sqlx::query("DELETE FROM quote_revisions WHERE quote_id = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok((StatusCode::OK, serde_json::to_string(&quote).unwrap()))
+    } else {
+        Err(ApiError::not_found("Quote not found").into())
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/19/undo/{id}",
+    tag = "quotes",
+    params(("id" = Uuid, Path, description = "Quote ID"), ("If-Match" = Option<String>, Header, description = "Expected quote version")),
+    request_body = Draft,
+    responses(
+        (status = 200, description = "The quote as JSON", body = Quote, content_type = "text/plain"),
+        (status = 400, description = "Invalid request", body = ApiErrorResponse),
+        (status = 404, description = "Quote not found", body = ApiErrorResponse),
+        (status = 409, description = "If-Match version is stale", body = ApiErrorResponse),
+        (status = 429, description = "Too many writes from this client", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all, fields(quote_id))]
+async fn undo_quotes(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    id: Result<Path<Uuid>, PathRejection>,
+    draft: Result<Json<Draft>, JsonRejection>,
+) -> Result<(StatusCode, String), AppError> {
+    let Path(id) = id?;
+    Span::current().record("quote_id", tracing::field::display(id));
+    let Json(draft) = draft?;
+    let expected_version = if_match_version(&headers)?;
+    draft
+        .validate(&state.draft_limits)
+        .map_err(validation_error)?;
+    let mut tx = state.pool.begin().await?;
+    let quote = sqlx::query_as::<_, Quote>(
+        "UPDATE quotes SET quote = $1, author = $2, version = version + 1 \
+         WHERE id = $3 AND ($4::int IS NULL OR version = $4) \
+         RETURNING id, author, quote, created_at, version",
+    )
+    .bind(&draft.quote)
+    .bind(&draft.author)
+    .bind(id)
+    .bind(expected_version)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(quote) = quote {
+        record_revision(&mut tx, &quote).await?;
+        tx.commit().await?;
+        Ok((StatusCode::OK, serde_json::to_string(&quote).unwrap()))
+    } else {
+        Err(update_miss(&mut tx, id, expected_version).await)
+    }
+}
+
+// If-Matchにはバージョン番号を指定する（ETagのように引用符で囲んでもよい）
+fn if_match_version(headers: &HeaderMap) -> Result<Option<i32>, ApiError> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .map(|v| v.trim().trim_matches('"'))
+        .and_then(|v| v.parse().ok())
+        .map(Some)
+        .ok_or_else(|| ApiError::bad_request("If-Match must be a quote version"))
+}
+
+// 条件付きのUPDATEが0行だったとき、引用がないのかバージョンが違うのかを区別する
+async fn update_miss(
+    tx: &mut sqlx::PgConnection,
+    id: Uuid,
+    expected_version: Option<i32>,
+) -> AppError {
+    if expected_version.is_none() {
+        return ApiError::not_found("Quote not found").into();
+    }
+    match repository::quote_exists(tx, id).await {
+        Ok(true) => ApiError::conflict("Quote was modified by another request").into(),
+        Ok(false) => ApiError::not_found("Quote not found").into(),
+        Err(e) => e.into(),
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct IdempotentQuote {
+    #[sqlx(flatten)]
+    quote: Quote,
+    draft_author: Option<String>,
+    draft_quote: Option<String>,
+}
+
+async fn find_idempotent_quote(
+    pool: &sqlx::PgPool,
+    key: &str,
+    draft: &Draft,
+) -> Result<Option<Quote>, AppError> {
+    let existing = sqlx::query_as::<_, IdempotentQuote>(
+        "SELECT q.id, q.author, q.quote, q.created_at, q.version, \
+         k.author AS draft_author, k.quote AS draft_quote \
+         FROM idempotency_keys k JOIN quotes q ON q.id = k.quote_id \
+         WHERE k.key = $1 AND k.created_at > now() - make_interval(secs => $2)",
+    )
+    .bind(key)
+    .bind(IDEMPOTENCY_KEY_TTL.as_secs_f64())
+    .fetch_optional(pool)
+    .await?;
+    let Some(existing) = existing else {
+        return Ok(None);
+    };
+    // 内容を保存する前に記録されたキーは比較できないので、そのまま一致とみなす
+    let same_payload = existing.draft_author.is_none_or(|a| a == draft.author)
+        && existing.draft_quote.is_none_or(|q| q == draft.quote);
+    if !same_payload {
+        return Err(ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "idempotency_key_reused",
+            "Idempotency-Key was already used with a different payload",
+        )
+        .into());
+    }
+    Ok(Some(existing.quote))
+}
+
+#[utoipa::path(
+    post,
+    path = "/19/draft",
+    tag = "quotes",
+    params(("Idempotency-Key" = Option<String>, Header, description = "Replays return the original quote")),
+    request_body = Draft,
+    responses(
+        (status = 201, description = "The new quote as JSON", body = Quote, content_type = "text/plain"),
+        (status = 200, description = "Replayed Idempotency-Key", body = Quote, content_type = "text/plain"),
+        (status = 400, description = "Invalid request", body = ApiErrorResponse),
+        (status = 422, description = "Idempotency-Key reused with a different draft", body = ApiErrorResponse),
+        (status = 429, description = "Too many writes from this client", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn add_quote(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    draft: Result<Json<Draft>, JsonRejection>,
+) -> Result<(StatusCode, String), AppError> {
+    let Json(draft) = draft?;
+    draft
+        .validate(&state.draft_limits)
+        .map_err(validation_error)?;
+    let idempotency_key = headers
+        .get("Idempotency-Key")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+
+    // 同じキーで既に作成済みなら、その引用をそのまま返す
+    if let Some(key) = &idempotency_key {
+        if let Some(quote) = find_idempotent_quote(&state.pool, key, &draft).await? {
+            return Ok((StatusCode::OK, serde_json::to_string(&quote).unwrap()));
+        }
+    }
+
+    let mut tx = state.pool.begin().await?;
+    // IDはDBの既定値（v4）ではなく、時刻順に並ぶv7をアプリ側で生成する
+    let quote = sqlx::query_as::<_, Quote>(
+        "INSERT INTO quotes (id, quote, author, created_at) \
+         VALUES ($1, $2, $3, COALESCE($4, CURRENT_TIMESTAMP)) \
+         RETURNING id, author, quote, created_at, version",
+    )
+    .bind(Uuid::now_v7())
+    .bind(&draft.quote)
+    .bind(&draft.author)
+    .bind(draft.created_at)
+    .fetch_one(&mut *tx)
+    .await?;
+    record_revision(&mut tx, &quote).await?;
+    if let Some(key) = &idempotency_key {
+        // 期限切れでまだ掃除されていないキーは、新しいリクエストのために空けておく
+        sqlx::query(
+            "DELETE FROM idempotency_keys \
+             WHERE key = $1 AND created_at <= now() - make_interval(secs => $2)",
+        )
+        .bind(key)
+        .bind(IDEMPOTENCY_KEY_TTL.as_secs_f64())
+        .execute(&mut *tx)
+        .await?;
+        let inserted = sqlx::query(
+            "INSERT INTO idempotency_keys (key, quote_id, author, quote) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(key)
+        .bind(quote.id)
+        .bind(&draft.author)
+        .bind(&draft.quote)
+        .execute(&mut *tx)
+        .await;
+        // 同じキーのリクエストと競合した場合は、先に記録された方の結果を返す
+        if let Err(e) = inserted {
+            if !e
+                .as_database_error()
+                .is_some_and(|db_err| db_err.is_unique_violation())
+            {
+                return Err(e.into());
+            }
+            tx.rollback().await?;
+            return match find_idempotent_quote(&state.pool, key, &draft).await? {
+                Some(quote) => Ok((StatusCode::OK, serde_json::to_string(&quote).unwrap())),
+                None => Err(ApiError::conflict("Conflict").into()),
+            };
+        }
+    }
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, serde_json::to_string(&quote).unwrap()))
+}
+
+const MAX_BATCH_SIZE: usize = 500;
+
+#[derive(Serialize, ToSchema)]
+struct BatchError {
+    index: usize,
+    reason: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/19/draft/batch",
+    tag = "quotes",
+    request_body = Vec<Draft>,
+    responses(
+        (status = 201, description = "All quotes created", body = Vec<Quote>),
+        (status = 400, description = "Invalid request", body = ApiErrorResponse),
+        (status = 429, description = "Too many writes from this client", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn add_quotes_batch(
+    State(state): State<AppState>,
+    entries: Result<Json<Vec<JsonValue>>, JsonRejection>,
+) -> Result<(StatusCode, Json<Vec<Quote>>), AppError> {
+    let Json(entries) = entries?;
+    if entries.is_empty() {
+        return Err(ApiError::bad_request("Batch must not be empty").into());
+    }
+    if entries.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::bad_request(format!(
+            "Batch must not exceed {} entries",
+            MAX_BATCH_SIZE
+        ))
+        .into());
+    }
+
+    // 1件でも不正なら全体を拒否し、不正な全件の理由を返す
+    let mut drafts = Vec::with_capacity(entries.len());
+    let mut errors = Vec::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        match serde_json::from_value::<Draft>(entry) {
+            Ok(draft) => match draft.validate(&state.draft_limits) {
+                Ok(()) => drafts.push(draft),
+                Err(field_errors) => errors.push(BatchError {
+                    index,
+                    reason: field_errors
+                        .into_iter()
+                        .map(|e| e.message)
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                }),
+            },
+            Err(e) => errors.push(BatchError {
+                index,
+                reason: format!("invalid draft: {}", e),
+            }),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::bad_request("Invalid batch")
+            .with_code("validation_failed")
+            .with_details(serde_json::to_value(errors).unwrap())
+            .into());
+    }
+
+    // 途中で失敗した場合はトランザクションがドロップされてロールバックされる
+    let mut tx = state.pool.begin().await?;
+    let mut quotes = Vec::with_capacity(drafts.len());
+    for draft in drafts {
+        let quote = sqlx::query_as::<_, Quote>(
+            "INSERT INTO quotes (id, quote, author, created_at) \
+             VALUES ($1, $2, $3, COALESCE($4, CURRENT_TIMESTAMP)) \
+             RETURNING id, author, quote, created_at, version",
+        )
+        .bind(Uuid::now_v7())
+        .bind(draft.quote)
+        .bind(draft.author)
+        .bind(draft.created_at)
+        .fetch_one(&mut *tx)
+        .await?;
+        record_revision(&mut tx, &quote).await?;
+        quotes.push(quote);
+    }
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(quotes)))
+}
+
+#[derive(sqlx::FromRow, Serialize, ToSchema)]
+struct AppliedMigration {
+    version: i64,
+    description: String,
+    applied_on: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/migrations",
+    tag = "quotes",
+    responses((status = 200, description = "Applied migrations", body = Vec<AppliedMigration>)),
+)]
+#[instrument(skip_all)]
+async fn list_migrations(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AppliedMigration>>, AppError> {
+    let migrations = sqlx::query_as::<_, AppliedMigration>(
+        "SELECT version, description, installed_on AS applied_on FROM _sqlx_migrations \
+         WHERE success ORDER BY version ASC",
+    )
+    .fetch_all(&state.pool)
+    .await?;
+    Ok(Json(migrations))
+}
+
+fn generate_token(rng: &mut impl Rng) -> String {
+    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+    let mut token = String::with_capacity(16);
+    for _ in 0..16 {
+        let idx = rng.gen_range(0..CHARSET.len());
+        token.push(CHARSET[idx] as char);
+    }
+    token
+}
+
+async fn issue_pagination_token(
+    state: &AppState,
+    pagination_state: PaginationState,
+) -> Result<String, sqlx::Error> {
+    let token = if let Some(counter) = &state.pagination_tokens.deterministic_counter {
+        let seq = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        format!("page-{}-{}", pagination_state.page, seq)
+    } else {
+        // トークンはDBに残るので、再起動しても同じ並びにならないよう共有のシード付きRNGは使わない
+        generate_token(&mut rand::thread_rng())
+    };
+    state
+        .pagination_tokens
+        .insert(&state.pool, &token, &pagination_state)
+        .await?;
+    Ok(token)
+}
+
+pub(crate) const DEFAULT_QUOTES_PER_PAGE: i64 = 3;
+pub(crate) const MAX_QUOTES_PER_PAGE: i64 = 100;
+// トークンを辿る場合はキーセットなので深いページでも全件走査にはならないが、
+// ?page=Nで直接指定された場合はOFFSETで読み飛ばすため、深いページほど遅くなる
+// どちらの場合もページ数に上限を設ける（MAX_QUOTES_PER_PAGE件ずつでも10万件まで辿れる）
+const MAX_PAGE: i32 = 1000;
+
+// 検索条件は全クエリで共通（NULLなら条件なし）
+const QUOTE_FILTERS: &str = "($1::text IS NULL OR author ILIKE $1 ESCAPE '\\') \
+     AND ($2::text IS NULL OR quote ILIKE $2 ESCAPE '\\') \
+     AND ($3::text IS NULL OR author = $3) \
+     AND ($4::timestamptz IS NULL OR created_at > $4) \
+     AND ($5::timestamptz IS NULL OR created_at < $5)";
+
+fn escape_like(input: &str) -> String {
+    input
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+async fn take_pagination_token(
+    state: &AppState,
+    token: &str,
+    endpoint: TokenEndpoint,
+) -> Result<PaginationState, AppError> {
+    let pagination_state = state
+        .pagination_tokens
+        .take(&state.pool, token)
+        .await?
+        .ok_or(ApiError::bad_request("Invalid token"))?;
+    // 検索条件や並び順が違うので、別のエンドポイントが発行したトークンは受け付けない
+    if pagination_state.endpoint != endpoint.name() {
+        return Err(ApiError::bad_request("Token was issued by a different endpoint").into());
+    }
+    Ok(pagination_state)
+}
+
+// limitは範囲外なら400、従来のper_pageは範囲内に丸める
+fn page_size(
+    limit: Option<i64>,
+    per_page: Option<i64>,
+    default_per_page: i64,
+) -> Result<i64, ApiError> {
+    match limit {
+        Some(limit) if !(1..=MAX_QUOTES_PER_PAGE).contains(&limit) => Err(ApiError::bad_request(
+            format!("limit must be between 1 and {}", MAX_QUOTES_PER_PAGE),
+        )),
+        Some(limit) => Ok(limit),
+        None => Ok(per_page
+            .unwrap_or(default_per_page)
+            .clamp(1, MAX_QUOTES_PER_PAGE)),
+    }
+}
+
+fn first_page(
+    endpoint: TokenEndpoint,
+    per_page: i64,
+    sort: QuoteSort,
+    order: SortOrder,
+    author_pattern: Option<String>,
+    quote_pattern: Option<String>,
+) -> PaginationState {
+    PaginationState {
+        endpoint: endpoint.name().to_string(),
+        page: 1,
+        per_page,
+        sort: sort.name().to_string(),
+        descending: matches!(order, SortOrder::Desc),
+        cursor_value: None,
+        cursor_id: None,
+        backward: false,
+        author_pattern,
+        quote_pattern,
+        author: None,
+        fts_query: None,
+        created_after: None,
+        created_before: None,
+    }
+}
+
+async fn fetch_quote_page(
+    state: &AppState,
+    pagination_state: PaginationState,
+) -> Result<QuoteList, AppError> {
+    let current_page = pagination_state.page;
+    let per_page = pagination_state.per_page;
+    if current_page > MAX_PAGE {
+        return Err(ApiError::bad_request(format!("page must not exceed {}", MAX_PAGE)).into());
+    }
+
+    // 並び順はトークンから復元するので、知らない名前なら黙って既定値にせず拒否する
+    let sort = QuoteSort::from_name(&pagination_state.sort)
+        .ok_or(ApiError::bad_request("Invalid token"))?;
+    // 前のページはカーソルより前を逆順に取得して並べ直す
+    let descending = pagination_state.descending != pagination_state.backward;
+    let (operator, direction) = if descending {
+        ("<", "DESC")
+    } else {
+        (">", "ASC")
+    };
+    let limit = if pagination_state.backward {
+        per_page
+    } else {
+        per_page + 1 // 次のページがあるかチェックするために1つ多く取得
+    };
+
+    // ページ番号で直接指定された場合だけカーソルがないので、そのときはOFFSETで読み飛ばす
+    let offset = if pagination_state.cursor_value.is_none() && !pagination_state.backward {
+        (current_page as i64 - 1) * per_page
+    } else {
+        0
+    };
+
+    // 通常はOFFSETではなく(ソート列, id)のキーセットで次のページを取得する
+    // 列名と型は許可リストのenumからのみ組み立てる
+    let page_sql = format!(
+        "SELECT * FROM quotes \
+         WHERE {filters} AND ($6::text IS NULL OR ({column}, id) {operator} ($6::{cast}, $7)) \
+         ORDER BY {column} {direction}, id {direction} LIMIT $8 OFFSET $9",
+        filters = QUOTE_FILTERS,
+        column = sort.name(),
+        cast = sort.sql_type(),
+        operator = operator,
+        direction = direction,
+    );
+    let page_query = sqlx::query_as::<_, Quote>(&page_sql)
+        .bind(&pagination_state.author_pattern)
+        .bind(&pagination_state.quote_pattern)
+        .bind(&pagination_state.author)
+        .bind(pagination_state.created_after)
+        .bind(pagination_state.created_before)
+        .bind(&pagination_state.cursor_value)
+        .bind(pagination_state.cursor_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&state.pool);
+    // 総件数も同じ検索条件で数える（ページの取得と並行して実行する）
+    let count_sql = format!("SELECT COUNT(*) FROM quotes WHERE {}", QUOTE_FILTERS);
+    let count_query = sqlx::query_scalar::<_, i64>(&count_sql)
+        .bind(&pagination_state.author_pattern)
+        .bind(&pagination_state.quote_pattern)
+        .bind(&pagination_state.author)
+        .bind(pagination_state.created_after)
+        .bind(pagination_state.created_before)
+        .fetch_one(&state.pool);
+    let (mut quotes, total) = tokio::try_join!(page_query, count_query)?;
+
+    let has_next_page = if pagination_state.backward {
+        quotes.reverse();
+        !quotes.is_empty()
+    } else {
+        let has_next_page = quotes.len() > per_page as usize;
+        quotes.truncate(per_page as usize);
+        has_next_page
+    };
+
+    // 切り上げ除算で総ページ数を求める
+    let total_pages = (total + per_page - 1) / per_page;
+
+    let next_token = match quotes.last() {
+        Some(last) if has_next_page && current_page < MAX_PAGE => Some(
+            issue_pagination_token(
+                state,
+                PaginationState {
+                    page: current_page + 1,
+                    cursor_value: Some(sort.cursor_value(last)),
+                    cursor_id: Some(last.id),
+                    backward: false,
+                    ..pagination_state.clone()
+                },
+            )
+            .await?,
+        ),
+        _ => None,
+    };
+    // 範囲外のページは戻る起点になる引用がないので、前のページもないものとして扱う
+    let has_prev = current_page > 1 && !quotes.is_empty();
+    let prev_token = match quotes.first() {
+        Some(first) if has_prev => Some(
+            issue_pagination_token(
+                state,
+                PaginationState {
+                    page: current_page - 1,
+                    cursor_value: Some(sort.cursor_value(first)),
+                    cursor_id: Some(first.id),
+                    backward: true,
+                    ..pagination_state.clone()
+                },
+            )
+            .await?,
+        ),
+        _ => None,
+    };
+
+    Ok(QuoteList {
+        quotes,
+        page: current_page,
+        limit: per_page,
+        next_token,
+        total,
+        total_pages,
+        has_prev,
+        prev_token,
+    })
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ListQuery {
+    token: Option<String>,
+    // 空文字（?page=）も区別できるよう文字列のまま受け取る
+    page: Option<String>,
+    limit: Option<i64>,
+    per_page: Option<i64>,
+    sort: Option<QuoteSort>,
+    order: Option<SortOrder>,
+    after: Option<String>,
+    before: Option<String>,
+}
+
+fn parse_rfc3339(name: &str, value: Option<&str>) -> Result<Option<DateTime<Utc>>, ApiError> {
+    value
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|dt| dt.with_timezone(&Utc))
+                .map_err(|_| {
+                    ApiError::bad_request(format!("{} must be an RFC 3339 timestamp", name))
+                })
+        })
+        .transpose()
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/list",
+    tag = "quotes",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "One page of quotes", body = QuoteList),
+        (status = 400, description = "Invalid request", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn list_quotes(
+    State(state): State<AppState>,
+    query: Result<Query<ListQuery>, QueryRejection>,
+) -> Result<Json<QuoteList>, AppError> {
+    let Query(query) = query?;
+    // ページサイズは最初のページでのみ指定でき、以降はトークンに引き継がれる
+    // tokenとpageの両方があればtokenを優先する
+    let pagination_state = if let Some(token) = &query.token {
+        take_pagination_token(&state, token, TokenEndpoint::List).await?
+    } else {
+        let page = match query.page.as_deref() {
+            None => 1,
+            Some(page) => page
+                .parse::<i32>()
+                .ok()
+                .filter(|page| *page >= 1)
+                .ok_or(ApiError::bad_request("page must be a positive integer"))?,
+        };
+        PaginationState {
+            page,
+            created_after: parse_rfc3339("after", query.after.as_deref())?,
+            created_before: parse_rfc3339("before", query.before.as_deref())?,
+            ..first_page(
+                TokenEndpoint::List,
+                page_size(query.limit, query.per_page, state.default_per_page)?,
+                query.sort.unwrap_or_default(),
+                query.order.unwrap_or_default(),
+                None,
+                None,
+            )
+        }
+    };
+    Ok(Json(fetch_quote_page(&state, pagination_state).await?))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SearchQuery {
+    author: Option<String>,
+    q: Option<String>,
+    page_token: Option<String>,
+    limit: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/search",
+    tag = "quotes",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "One page of matching quotes", body = QuoteList),
+        (status = 400, description = "Invalid request", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn search_quotes(
+    State(state): State<AppState>,
+    query: Result<Query<SearchQuery>, QueryRejection>,
+) -> Result<Json<QuoteList>, AppError> {
+    let Query(query) = query?;
+    let pagination_state = if let Some(token) = &query.page_token {
+        take_pagination_token(&state, token, TokenEndpoint::Search).await?
+    } else {
+        if query.author.is_none() && query.q.is_none() {
+            return Err(ApiError::bad_request(
+                "Specify author or q, or use /19/list to list all quotes",
+            )
+            .into());
+        }
+        // 作者は大文字小文字を無視した完全一致、本文は部分一致
+        let author_pattern = query.author.as_deref().map(escape_like);
+        let quote_pattern = query.q.as_deref().map(|q| format!("%{}%", escape_like(q)));
+        first_page(
+            TokenEndpoint::Search,
+            page_size(query.limit, query.per_page, state.default_per_page)?,
+            QuoteSort::default(),
+            SortOrder::default(),
+            author_pattern,
+            quote_pattern,
+        )
+    };
+    Ok(Json(fetch_quote_page(&state, pagination_state).await?))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ByAuthorQuery {
+    token: Option<String>,
+    limit: Option<i64>,
+    per_page: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/by-author/{author}",
+    tag = "quotes",
+    params(("author" = String, Path, description = "Exact author name"), ByAuthorQuery),
+    responses(
+        (status = 200, description = "One page of the author's quotes", body = QuoteList),
+        (status = 400, description = "Invalid request", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn list_quotes_by_author(
+    State(state): State<AppState>,
+    author: Result<Path<String>, PathRejection>,
+    query: Result<Query<ByAuthorQuery>, QueryRejection>,
+) -> Result<Json<QuoteList>, AppError> {
+    let Path(author) = author?;
+    let Query(query) = query?;
+    // パスはPathでデコード済みなので、空白や記号を含む作者名もそのまま比較できる
+    let pagination_state = if let Some(token) = &query.token {
+        let pagination_state =
+            take_pagination_token(&state, token, TokenEndpoint::ByAuthor).await?;
+        // 別の作者用のトークンは使えない
+        if pagination_state.author.as_deref() != Some(author.as_str()) {
+            return Err(ApiError::bad_request("Invalid token").into());
+        }
+        pagination_state
+    } else {
+        PaginationState {
+            author: Some(author),
+            ..first_page(
+                TokenEndpoint::ByAuthor,
+                page_size(query.limit, query.per_page, state.default_per_page)?,
+                QuoteSort::default(),
+                SortOrder::default(),
+                None,
+                None,
+            )
+        }
+    };
+    Ok(Json(fetch_quote_page(&state, pagination_state).await?))
+}
+
+#[derive(sqlx::FromRow, Serialize, ToSchema)]
+struct FtsMatch {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    quote: Quote,
+    rank: f32,
+    headline: String,
+}
+
+#[derive(Serialize, ToSchema)]
+struct FtsList {
+    results: Vec<FtsMatch>,
+    page: i32,
+    limit: i64,
+    next_token: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct FtsQuery {
+    q: Option<String>,
+    token: Option<String>,
+    limit: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/fts",
+    tag = "quotes",
+    params(FtsQuery),
+    responses(
+        (status = 200, description = "Ranked full-text matches", body = FtsList),
+        (status = 400, description = "Invalid request", body = ApiErrorResponse),
+    ),
+)]
+#[instrument(skip_all)]
+async fn fts_quotes(
+    State(state): State<AppState>,
+    query: Result<Query<FtsQuery>, QueryRejection>,
+) -> Result<Json<FtsList>, AppError> {
+    let Query(query) = query?;
+    let pagination_state = if let Some(token) = &query.token {
+        take_pagination_token(&state, token, TokenEndpoint::Fts).await?
+    } else {
+        let q = query.q.unwrap_or_default();
+        // 空文字やストップワードだけのクエリは何にも一致しないので400にする
+        let nodes: i32 = sqlx::query_scalar("SELECT numnode(websearch_to_tsquery('english', $1))")
+            .bind(&q)
+            .fetch_one(&state.pool)
+            .await?;
+        if nodes == 0 {
+            return Err(ApiError::bad_request("Invalid search query").into());
+        }
+        PaginationState {
+            sort: "rank".to_string(),
+            descending: true,
+            fts_query: Some(q),
+            ..first_page(
+                TokenEndpoint::Fts,
+                page_size(query.limit, None, state.default_per_page)?,
+                QuoteSort::default(),
+                SortOrder::Desc,
+                None,
+                None,
+            )
+        }
+    };
+    let current_page = pagination_state.page;
+    let per_page = pagination_state.per_page;
+    if current_page > MAX_PAGE {
+        return Err(ApiError::bad_request(format!("page must not exceed {}", MAX_PAGE)).into());
+    }
+
+    // (rank, id)の降順をキーセットとして次のページを取得する
+    let mut results = sqlx::query_as::<_, FtsMatch>(
+        "SELECT id, author, quote, created_at, version, rank, \
+         ts_headline('english', quote, query, 'StartSel=<mark>, StopSel=</mark>') AS headline \
+         FROM (SELECT q.*, ts_rank(q.tsv, query) AS rank, query \
+               FROM quotes q, websearch_to_tsquery('english', $1) query \
+               WHERE q.tsv @@ query) matches \
+         WHERE ($2::text IS NULL OR (rank, id) < ($2::real, $3)) \
+         ORDER BY rank DESC, id DESC LIMIT $4",
+    )
+    .bind(&pagination_state.fts_query)
+    .bind(&pagination_state.cursor_value)
+    .bind(pagination_state.cursor_id)
+    .bind(per_page + 1)
+    .fetch_all(&state.pool)
+    .await?;
+
+    let has_next_page = results.len() > per_page as usize;
+    results.truncate(per_page as usize);
+    let next_token = match results.last() {
+        Some(last) if has_next_page && current_page < MAX_PAGE => Some(
+            issue_pagination_token(
+                &state,
+                PaginationState {
+                    page: current_page + 1,
+                    cursor_value: Some(last.rank.to_string()),
+                    cursor_id: Some(last.quote.id),
+                    ..pagination_state
+                },
+            )
+            .await?,
+        ),
+        _ => None,
+    };
+
+    Ok(Json(FtsList {
+        results,
+        page: current_page,
+        limit: per_page,
+        next_token,
+    }))
+}
+
+fn csv_field(value: &str) -> String {
+    // カンマ・改行・ダブルクォートを含むフィールドはクォートする
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn quote_csv_row(quote: &Quote) -> String {
+    format!(
+        "{},{},{},{},{}\n",
+        quote.id,
+        csv_field(&quote.author),
+        csv_field(&quote.quote),
+        format_millis(&quote.created_at),
+        quote.version
+    )
+}
+
+#[derive(Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ExportQuery {
+    format: Option<ExportFormat>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/19/export",
+    tag = "quotes",
+    params(ExportQuery),
+    responses((
+        status = 200,
+        description = "All quotes as CSV or NDJSON",
+        content((String = "text/csv"), (String = "application/x-ndjson")),
+    )),
+)]
+#[instrument(skip_all)]
+async fn export_quotes(
+    State(state): State<AppState>,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(query) = query?;
+    let format = query.format.unwrap_or_default();
+    let pool = state.pool.clone();
+    // テーブル全体をメモリに載せないよう、1行ずつストリームで返す
+    let rows = async_stream::stream! {
+        if let ExportFormat::Csv = format {
+            yield Ok::<_, sqlx::Error>("id,author,quote,created_at,version\n".to_string());
+        }
+        let mut quotes =
+            sqlx::query_as::<_, Quote>("SELECT * FROM quotes ORDER BY created_at ASC, id ASC")
+                .fetch(&pool);
+        while let Some(quote) = quotes.next().await {
+            yield quote.map(|quote| match format {
+                ExportFormat::Csv => quote_csv_row(&quote),
+                ExportFormat::Ndjson => format!("{}\n", serde_json::to_string(&quote).unwrap()),
+            });
+        }
+    };
+
+    let (content_type, extension) = match format {
+        ExportFormat::Csv => ("text/csv", "csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(
+        header::CONTENT_DISPOSITION,
+        HeaderValue::from_str(&format!(
+            "attachment; filename=\"quotes-{}.{}\"",
+            Utc::now().format("%Y-%m-%d"),
+            extension
+        ))
+        .unwrap(),
+    );
+    Ok((headers, Body::from_stream(rows)).into_response())
+}
+
+// TRUSTED_PROXIESはカンマ区切りのIPアドレス
+pub fn parse_trusted_proxies(value: &str) -> Result<Vec<IpAddr>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            v.parse()
+                .map_err(|_| format!("TRUSTED_PROXIES contains an invalid IP address: {}", v))
+        })
+        .collect()
+}
+
+// 通常は接続元のIPを使う。X-Forwarded-Forは誰でも書けるので、接続元が信頼できる
+// プロキシのときだけ右から辿り、最初に現れた信頼できないアドレスをクライアントとみなす
+fn client_id(request: &Request, trusted_proxies: &[IpAddr]) -> String {
+    let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<SocketAddr>>() else {
+        return "unknown".to_string();
+    };
+    let mut client = peer.ip();
+    if trusted_proxies.contains(&client) {
+        let hops = request
+            .headers()
+            .get_all("X-Forwarded-For")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect::<Vec<_>>();
+        for hop in hops.into_iter().rev() {
+            // 読めない値があればそれより左は信用できないので、最後に確認できたアドレスを使う
+            let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+                break;
+            };
+            client = hop;
+            if !trusted_proxies.contains(&hop) {
+                break;
+            }
+        }
+    }
+    client.to_string()
+}
+
+async fn limit_quote_writes(
+    State(state): State<AppState>,
+    request: Request,
+    next: middleware::Next,
+) -> Response {
+    // 読み取り系のメソッドは制限しない
+    if request.method().is_safe() {
+        return next.run(request).await;
+    }
+    if !state
+        .write_limiter
+        .try_acquire(&client_id(&request, &state.trusted_proxies))
+    {
+        return AppError::TooManyRequests {
+            message: "Too many quote writes".to_string(),
+            retry_after_secs: state.write_limiter.retry_after_secs(),
+        }
+        .into_response();
+    }
+    next.run(request).await
+}
+
+// 引用の書き込み系ルートはクライアントごとにレート制限する
+#[derive(OpenApi)]
+#[openapi(paths(
+    reset_quotes,
+    delete_all_quotes,
+    collect_pagination_tokens,
+    get_quotes,
+    patch_quote,
+    get_quote_raw,
+    remove_quotes,
+    undo_quotes,
+    get_quote_history,
+    get_quote_total,
+    get_quote_stats,
+    list_authors,
+    add_quote,
+    add_quotes_batch,
+    list_quotes,
+    search_quotes,
+    fts_quotes,
+    list_quotes_by_author,
+    export_quotes,
+    list_migrations,
+))]
+pub(crate) struct ApiDoc;
+
+pub(crate) fn routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/19/reset", post(reset_quotes))
+        .route("/19/quotes", delete(delete_all_quotes))
+        .route("/19/gc", post(collect_pagination_tokens))
+        .route("/19/cite/:id", get(get_quotes).patch(patch_quote))
+        .route("/19/cite/:id/raw", get(get_quote_raw))
+        .route("/19/remove/:id", delete(remove_quotes))
+        .route("/19/undo/:id", put(undo_quotes))
+        .route("/19/history/:id", get(get_quote_history))
+        .route("/19/total", get(get_quote_total))
+        .route("/19/stats", get(get_quote_stats))
+        .route("/19/authors", get(list_authors))
+        .route("/19/draft", post(add_quote))
+        .route("/19/draft/batch", post(add_quotes_batch))
+        .route("/19/list", get(list_quotes))
+        .route("/19/search", get(search_quotes))
+        .route("/19/fts", get(fts_quotes))
+        .route("/19/by-author/:author", get(list_quotes_by_author))
+        .route("/19/export", get(export_quotes))
+        .route("/19/migrations", get(list_migrations))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            limit_quote_writes,
+        ))
+}
+
+pub fn spawn_sweep_tasks(state: &AppState) {
+    // 期限切れのページネーショントークンを定期的に掃除する
+    let pagination_tokens = state.pagination_tokens.clone();
+    let sweep_pool = state.pool.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(TOKEN_SWEEP_INTERVAL));
+        loop {
+            interval.tick().await;
+            let swept = pagination_tokens.sweep(&sweep_pool).await;
+            let live = pagination_tokens.len(&sweep_pool).await;
+            match (swept, live) {
+                (Ok(swept), Ok(live)) => {
+                    tracing::info!(swept, live, "pagination token sweep")
+                }
+                (Err(e), _) | (_, Err(e)) => {
+                    tracing::error!(error = ?e, "pagination token sweep failed")
+                }
+            }
+        }
+    });
+
+    // 24時間を過ぎたIdempotency-Keyは定期的に削除する
+    let idempotency_pool = state.pool.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(IDEMPOTENCY_SWEEP_INTERVAL));
+        loop {
+            interval.tick().await;
+            let swept = sqlx::query(
+                "DELETE FROM idempotency_keys WHERE created_at <= now() - make_interval(secs => $1)",
+            )
+            .bind(IDEMPOTENCY_KEY_TTL.as_secs_f64())
+            .execute(&idempotency_pool)
+            .await;
+            match swept {
+                Ok(result) => {
+                    tracing::info!(swept = result.rows_affected(), "idempotency key sweep")
+                }
+                Err(e) => tracing::error!(error = ?e, "idempotency key sweep failed"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        client_id, parse_trusted_proxies, Draft, DraftLimits, PaginationState, PaginationTokens,
+        WriteLimiter, DEFAULT_MAX_AUTHOR_CHARS, MAX_PAGE,
+    };
+    use crate::{testing::*, AppConfig};
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{
+            header::{self, CONTENT_TYPE},
+            StatusCode,
+        },
+        Router,
+    };
+    use chrono::{DateTime, Utc};
+    use serde_json::Value as JsonValue;
+    use std::{
+        net::{IpAddr, SocketAddr},
+        time::Duration,
+    };
+    use uuid::Uuid;
+
+    // 書き込みのレート制限に引っかからないよう、バケットを大きくしておく
+    fn quote_router(pool: sqlx::PgPool) -> Router {
+        router_with(
+            pool,
+            AppConfig {
+                write_bucket_size: 1000,
+                ..AppConfig::default()
+            },
+        )
+    }
+
+    async fn add_quote(router: &Router, author: &str, quote: &str) -> JsonValue {
+        let draft = serde_json::json!({ "author": author, "quote": quote });
+        let response = send(router, post_json("/19/draft", draft)).await;
+        assert_eq!(response.status, StatusCode::CREATED);
+        response.json()
+    }
+
+    async fn count_quotes(pool: &sqlx::PgPool) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM quotes")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn delete_all_quotes_requires_confirmation(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        add_quote(&router, "Santa", "Ho ho ho").await;
+        add_quote(&router, "Rudolph", "Shiny").await;
+
+        let response = send(
+            &router,
+            request("DELETE", "/19/quotes", None, Body::empty()),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(count_quotes(&pool).await, 2);
+
+        let response = send(
+            &router,
+            request("DELETE", "/19/quotes?confirm=true", None, Body::empty()),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json(), serde_json::json!({ "deleted": 2 }));
+        assert_eq!(count_quotes(&pool).await, 0);
+    }
+
+    #[sqlx::test]
+    async fn delete_all_quotes_accepts_confirmation_header(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        add_quote(&router, "Santa", "Ho ho ho").await;
+
+        let mut request = request("DELETE", "/19/quotes", None, Body::empty());
+        request
+            .headers_mut()
+            .insert("X-Confirm-Delete", "true".parse().unwrap());
+        let response = send(&router, request).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json(), serde_json::json!({ "deleted": 1 }));
+    }
+
+    async fn list_page(router: &Router, uri: &str) -> JsonValue {
+        let response = send(router, get(uri)).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+        response.json()
+    }
+
+    fn quote_texts(page: &JsonValue) -> Vec<&str> {
+        page["quotes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|quote| quote["quote"].as_str().unwrap())
+            .collect()
+    }
+
+    #[sqlx::test]
+    async fn list_metadata_across_pages(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=7 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+
+        let first = list_page(&router, "/19/list").await;
+        assert_eq!(quote_texts(&first), ["quote 1", "quote 2", "quote 3"]);
+        assert_eq!(first["page"], 1);
+        assert_eq!(first["total"], 7);
+        assert_eq!(first["total_pages"], 3);
+        assert_eq!(first["has_prev"], false);
+        assert!(first["prev_token"].is_null());
+        let token = first["next_token"].as_str().unwrap();
+
+        let middle = list_page(&router, &format!("/19/list?token={}", token)).await;
+        assert_eq!(quote_texts(&middle), ["quote 4", "quote 5", "quote 6"]);
+        assert_eq!(middle["page"], 2);
+        assert_eq!(middle["total"], 7);
+        assert_eq!(middle["total_pages"], 3);
+        assert_eq!(middle["has_prev"], true);
+        assert!(middle["prev_token"].is_string());
+        let token = middle["next_token"].as_str().unwrap();
+
+        let last = list_page(&router, &format!("/19/list?token={}", token)).await;
+        assert_eq!(quote_texts(&last), ["quote 7"]);
+        assert_eq!(last["page"], 3);
+        assert_eq!(last["total_pages"], 3);
+        assert_eq!(last["has_prev"], true);
+        assert!(last["next_token"].is_null());
+
+        // 最後のページから前に戻ると真ん中のページと同じ内容になる
+        let token = last["prev_token"].as_str().unwrap();
+        let back = list_page(&router, &format!("/19/list?token={}", token)).await;
+        assert_eq!(back["page"], 2);
+        assert_eq!(quote_texts(&back), quote_texts(&middle));
+    }
+
+    #[sqlx::test]
+    async fn per_page_is_carried_through_tokens(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=12 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+
+        let first = list_page(&router, "/19/list?per_page=5").await;
+        assert_eq!(quote_texts(&first).len(), 5);
+        assert_eq!(first["total_pages"], 3);
+        let token = first["next_token"].as_str().unwrap();
+
+        // 2ページ目以降はper_pageを付けなくても最初のサイズのまま
+        let second = list_page(&router, &format!("/19/list?token={}", token)).await;
+        assert_eq!(
+            quote_texts(&second),
+            ["quote 6", "quote 7", "quote 8", "quote 9", "quote 10"]
+        );
+        let token = second["next_token"].as_str().unwrap();
+        let third = list_page(&router, &format!("/19/list?token={}", token)).await;
+        assert_eq!(quote_texts(&third), ["quote 11", "quote 12"]);
+        assert!(third["next_token"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn per_page_is_clamped(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=3 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+
+        let page = list_page(&router, "/19/list?per_page=0").await;
+        assert_eq!(quote_texts(&page), ["quote 1"]);
+        assert_eq!(page["total_pages"], 3);
+
+        let page = list_page(&router, "/19/list?per_page=1000").await;
+        assert_eq!(quote_texts(&page).len(), 3);
+        assert!(page["next_token"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn closed_pool_returns_internal_error(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        pool.close().await;
+
+        let draft = serde_json::json!({ "author": "Santa", "quote": "Ho ho ho" });
+        let response = send(&router, post_json("/19/draft", draft)).await;
+        assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            response.json(),
+            serde_json::json!({ "error": { "code": "internal", "message": "Internal server error" } })
+        );
+
+        let id = Uuid::nil();
+        for request in [
+            get(&format!("/19/cite/{}", id)),
+            request("DELETE", &format!("/19/remove/{}", id), None, Body::empty()),
+            request(
+                "PUT",
+                &format!("/19/undo/{}", id),
+                Some("application/json"),
+                Body::from(r#"{"author":"Santa","quote":"Ho"}"#),
+            ),
+            post("/19/reset", "text/plain", ""),
+            get("/19/list"),
+        ] {
+            let response = send(&router, request).await;
+            assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    fn pagination_state(page: i32) -> PaginationState {
+        PaginationState {
+            endpoint: "list".to_string(),
+            page,
+            per_page: 3,
+            sort: "created_at".to_string(),
+            descending: false,
+            cursor_value: None,
+            cursor_id: None,
+            backward: false,
+            author_pattern: None,
+            quote_pattern: None,
+            author: None,
+            fts_query: None,
+            created_after: None,
+            created_before: None,
+        }
+    }
+
+    #[sqlx::test]
+    async fn pagination_tokens_are_capped_oldest_first(pool: sqlx::PgPool) {
+        let tokens = PaginationTokens {
+            ttl: Duration::from_secs(60),
+            retention: Duration::from_secs(60),
+            max_tokens: 2,
+            deterministic_counter: None,
+        };
+        for (page, token) in [(1, "a"), (2, "b"), (3, "c")] {
+            tokens
+                .insert(&pool, token, &pagination_state(page))
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(tokens.len(&pool).await.unwrap(), 2);
+        assert!(tokens.take(&pool, "a").await.unwrap().is_none());
+        assert_eq!(tokens.take(&pool, "c").await.unwrap().unwrap().page, 3);
+        // 一度使ったトークンは削除される
+        assert!(tokens.take(&pool, "c").await.unwrap().is_none());
+    }
+
+    #[sqlx::test]
+    async fn expired_pagination_tokens_are_swept(pool: sqlx::PgPool) {
+        let tokens = PaginationTokens {
+            ttl: Duration::ZERO,
+            retention: Duration::ZERO,
+            max_tokens: 10,
+            deterministic_counter: None,
+        };
+        let state = pagination_state(2);
+        tokens.insert(&pool, "old", &state).await.unwrap();
+        tokens.insert(&pool, "new", &state).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert!(tokens.take(&pool, "new").await.unwrap().is_none());
+        assert_eq!(tokens.sweep(&pool).await.unwrap(), 1);
+        assert_eq!(tokens.len(&pool).await.unwrap(), 0);
+    }
+
+    #[sqlx::test]
+    async fn tokens_survive_a_restart(pool: sqlx::PgPool) {
+        let before = quote_router(pool.clone());
+        for i in 1..=4 {
+            add_quote(&before, "Santa", &format!("quote {}", i)).await;
+        }
+        let first = list_page(&before, "/19/list").await;
+        let token = first["next_token"].as_str().unwrap();
+
+        let after = quote_router(pool);
+        let second = list_page(&after, &format!("/19/list?token={}", token)).await;
+        assert_eq!(quote_texts(&second), ["quote 4"]);
+
+        let response = send(&after, get(&format!("/19/list?token={}", token))).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn gc_purges_tokens_past_their_retention(pool: sqlx::PgPool) {
+        let config = |retention| AppConfig {
+            pagination_token_ttl: Duration::ZERO,
+            pagination_token_retention: retention,
+            ..AppConfig::default()
+        };
+        let router = router_with(pool.clone(), config(Duration::from_secs(3600)));
+        for i in 1..=4 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+        list_page(&router, "/19/list").await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // 有効期限は切れていても、保持期間内のトークンは残す
+        let gc = || request("POST", "/19/gc", None, Body::empty());
+        let response = send(&router, gc()).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json(), serde_json::json!({ "purged": 0 }));
+
+        let router = router_with(pool, config(Duration::ZERO));
+        let response = send(&router, gc()).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json(), serde_json::json!({ "purged": 1 }));
+        let response = send(&router, gc()).await;
+        assert_eq!(response.json(), serde_json::json!({ "purged": 0 }));
+    }
+
+    #[sqlx::test]
+    async fn expired_token_is_rejected(pool: sqlx::PgPool) {
+        let router = router_with(
+            pool,
+            AppConfig {
+                pagination_token_ttl: Duration::ZERO,
+                ..AppConfig::default()
+            },
+        );
+        for i in 1..=4 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+
+        let first = list_page(&router, "/19/list").await;
+        let token = first["next_token"].as_str().unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let response = send(&router, get(&format!("/19/list?token={}", token))).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    fn draft_with_key(key: &str, draft: &JsonValue) -> Request {
+        let mut request = post_json("/19/draft", draft.clone());
+        request
+            .headers_mut()
+            .insert("Idempotency-Key", key.parse().unwrap());
+        request
+    }
+
+    #[sqlx::test]
+    async fn idempotency_key_creates_one_quote(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        let draft = serde_json::json!({ "author": "Santa", "quote": "Ho ho ho" });
+
+        let first = send(&router, draft_with_key("retry-1", &draft)).await;
+        assert_eq!(first.status, StatusCode::CREATED);
+        let second = send(&router, draft_with_key("retry-1", &draft)).await;
+        assert_eq!(second.status, StatusCode::OK);
+        assert_eq!(second.json()["id"], first.json()["id"]);
+        assert_eq!(count_quotes(&pool).await, 1);
+
+        // 別のキーやキーなしでは新しく作られる
+        let third = send(&router, draft_with_key("retry-2", &draft)).await;
+        assert_eq!(third.status, StatusCode::CREATED);
+        assert_ne!(third.json()["id"], first.json()["id"]);
+        add_quote(&router, "Santa", "Ho ho ho").await;
+        assert_eq!(count_quotes(&pool).await, 3);
+    }
+
+    #[sqlx::test]
+    async fn inserts_mid_pagination_cause_no_duplicates_or_gaps(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        for i in 1..=6 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+
+        let first = list_page(&router, "/19/list").await;
+        let mut seen: Vec<String> = quote_texts(&first).into_iter().map(String::from).collect();
+
+        // 既に読んだ範囲より前と、まだ読んでいない範囲の後ろに1件ずつ追加する
+        sqlx::query("INSERT INTO quotes (author, quote, created_at) VALUES ($1, $2, $3)")
+            .bind("Grinch")
+            .bind("early")
+            .bind(DateTime::<Utc>::UNIX_EPOCH)
+            .execute(&pool)
+            .await
+            .unwrap();
+        add_quote(&router, "Santa", "quote 7").await;
+
+        let mut token = first["next_token"].as_str().map(String::from);
+        while let Some(next) = token {
+            let page = list_page(&router, &format!("/19/list?token={}", next)).await;
+            seen.extend(quote_texts(&page).into_iter().map(String::from));
+            token = page["next_token"].as_str().map(String::from);
+        }
+        let expected: Vec<String> = (1..=7).map(|i| format!("quote {}", i)).collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[sqlx::test]
+    async fn search_escapes_like_wildcards(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        add_quote(&router, "Santa", "100% jolly").await;
+        add_quote(&router, "Santa", "1000 reindeer").await;
+        add_quote(&router, "Elf_1", "snow_day").await;
+        add_quote(&router, "Elf21", "snowyday").await;
+
+        let page = list_page(&router, "/19/search?q=100%25").await;
+        assert_eq!(quote_texts(&page), ["100% jolly"]);
+        assert_eq!(page["total"], 1);
+
+        let page = list_page(&router, "/19/search?q=snow_day").await;
+        assert_eq!(quote_texts(&page), ["snow_day"]);
+
+        let page = list_page(&router, "/19/search?author=elf_1").await;
+        assert_eq!(quote_texts(&page), ["snow_day"]);
+
+        let page = list_page(&router, "/19/search?q=%25").await;
+        assert_eq!(quote_texts(&page), ["100% jolly"]);
+        assert!(page["next_token"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn search_pages_and_requires_a_filter(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=4 {
+            add_quote(&router, "Santa", &format!("reindeer {}", i)).await;
+        }
+        add_quote(&router, "Rudolph", "reindeer games").await;
+
+        let first = list_page(&router, "/19/search?author=SANTA&q=reindeer").await;
+        assert_eq!(quote_texts(&first).len(), 3);
+        assert_eq!(first["total"], 4);
+        let token = first["next_token"].as_str().unwrap();
+        let second = list_page(&router, &format!("/19/search?page_token={}", token)).await;
+        assert_eq!(quote_texts(&second), ["reindeer 4"]);
+
+        let empty = list_page(&router, "/19/search?q=sleigh").await;
+        assert!(quote_texts(&empty).is_empty());
+        assert!(empty["next_token"].is_null());
+
+        let response = send(&router, get("/19/search")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert!(response.text().contains("/19/list"));
+    }
+
+    #[sqlx::test]
+    async fn descending_author_order_is_kept_across_pages(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for author in [
+            "Dasher", "Blitzen", "Comet", "Vixen", "Cupid", "Dancer", "Comet",
+        ] {
+            add_quote(&router, author, &format!("{} says hi", author)).await;
+        }
+
+        let first = list_page(&router, "/19/list?sort=author&order=desc").await;
+        let mut authors: Vec<String> = Vec::new();
+        let mut page = first;
+        loop {
+            authors.extend(
+                page["quotes"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|quote| quote["author"].as_str().unwrap().to_string()),
+            );
+            // 2ページ目以降はパラメータを付けなくてもトークンの並び順に従う
+            match page["next_token"].as_str() {
+                Some(token) => {
+                    page = list_page(&router, &format!("/19/list?token={}", token)).await
+                }
+                None => break,
+            }
+        }
+        assert_eq!(
+            authors,
+            ["Vixen", "Dasher", "Dancer", "Cupid", "Comet", "Comet", "Blitzen"]
+        );
+    }
+
+    #[sqlx::test]
+    async fn unknown_sort_is_rejected(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let response = send(&router, get("/19/list?sort=id;DROP%20TABLE%20quotes")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        let response = send(&router, get("/19/list?order=sideways")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn export_quotes_fields_with_commas(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let quote = add_quote(&router, "Santa", "Ho, ho, ho").await;
+
+        let response = send(&router, get("/19/export")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers["content-type"], "text/csv");
+        let disposition = response.headers["content-disposition"].to_str().unwrap();
+        assert!(disposition.starts_with("attachment; filename=\"quotes-"));
+        assert!(disposition.ends_with(".csv\""));
+        let text = response.text();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "id,author,quote,created_at,version");
+        assert!(
+            lines[1].starts_with(&format!(
+                "{},Santa,\"Ho, ho, ho\",",
+                quote["id"].as_str().unwrap()
+            )),
+            "{}",
+            lines[1]
+        );
+        assert!(lines[1].ends_with(",1"));
+    }
+
+    #[sqlx::test]
+    async fn limit_is_validated_and_kept_in_the_token(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=7 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+
+        let first = list_page(&router, "/19/list?limit=5").await;
+        assert_eq!(quote_texts(&first).len(), 5);
+        assert_eq!(first["limit"], 5);
+        let token = first["next_token"].as_str().unwrap();
+        let second = list_page(&router, &format!("/19/list?token={}", token)).await;
+        assert_eq!(quote_texts(&second), ["quote 6", "quote 7"]);
+        assert_eq!(second["limit"], 5);
+
+        let search = list_page(&router, "/19/search?q=quote&limit=4").await;
+        assert_eq!(quote_texts(&search).len(), 4);
+        assert_eq!(search["limit"], 4);
+
+        let default = list_page(&router, "/19/list").await;
+        assert_eq!(default["limit"], 3);
+
+        for uri in [
+            "/19/list?limit=0",
+            "/19/list?limit=101",
+            "/19/search?q=x&limit=0",
+        ] {
+            let response = send(&router, get(uri)).await;
+            assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert_eq!(
+                response.json()["error"]["message"],
+                "limit must be between 1 and 100"
+            );
+        }
+    }
+
+    #[sqlx::test]
+    async fn deterministic_tokens_are_stable(pool: sqlx::PgPool) {
+        let router = router_with(
+            pool,
+            AppConfig {
+                deterministic_tokens: true,
+                ..AppConfig::default()
+            },
+        );
+        for i in 1..=7 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+
+        let first = list_page(&router, "/19/list").await;
+        assert_eq!(first["next_token"], "page-2-1");
+        let second = list_page(&router, "/19/list?token=page-2-1").await;
+        assert_eq!(second["next_token"], "page-3-2");
+        assert_eq!(second["prev_token"], "page-1-3");
+        let third = list_page(&router, "/19/list?token=page-3-2").await;
+        assert_eq!(quote_texts(&third), ["quote 7"]);
+    }
+
+    #[sqlx::test]
+    async fn batch_inserts_all_or_nothing(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        let batch = serde_json::json!([
+            { "author": "Santa", "quote": "Ho ho ho" },
+            { "author": "Rudolph", "quote": "Shiny" },
+        ]);
+        let response = send(&router, post_json("/19/draft/batch", batch)).await;
+        assert_eq!(response.status, StatusCode::CREATED);
+        let authors: Vec<JsonValue> = response
+            .json()
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|quote| quote["author"].clone())
+            .collect();
+        assert_eq!(authors, ["Santa", "Rudolph"]);
+
+        let batch = serde_json::json!([
+            { "author": "Santa", "quote": "Ho ho ho" },
+            { "author": " ", "quote": "Shiny" },
+            { "author": "Elf" },
+        ]);
+        let response = send(&router, post_json("/19/draft/batch", batch)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        let errors = response.json()["error"]["details"].clone();
+        assert_eq!(errors[0]["index"], 1);
+        assert_eq!(errors[0]["reason"], "author must not be empty");
+        assert_eq!(errors[1]["index"], 2);
+        assert_eq!(errors.as_array().unwrap().len(), 2);
+
+        // 検証は通るがDBで失敗する（NUL文字）場合も、先に挿入した行は残らない
+        let batch = serde_json::json!([
+            { "author": "Santa", "quote": "Ho ho ho" },
+            { "author": "Grinch", "quote": "bad\u{0}" },
+        ]);
+        let response = send(&router, post_json("/19/draft/batch", batch)).await;
+        assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(count_quotes(&pool).await, 2);
+
+        let response = send(&router, post_json("/19/draft/batch", serde_json::json!([]))).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        let oversized = vec![serde_json::json!({ "author": "Elf", "quote": "Hi" }); 501];
+        let response = send(&router, post_json("/19/draft/batch", oversized.into())).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(count_quotes(&pool).await, 2);
+    }
+
+    // テスト用の最小限のCSVパーサー（クォート内のカンマ・改行・""に対応）
+    fn parse_csv(text: &str) -> Vec<Vec<String>> {
+        let mut records = Vec::new();
+        let mut record = Vec::new();
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, in_quotes) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                ('"', _) => in_quotes = !in_quotes,
+                (',', false) => record.push(std::mem::take(&mut field)),
+                ('\n', false) => {
+                    record.push(std::mem::take(&mut field));
+                    records.push(std::mem::take(&mut record));
+                }
+                (c, _) => field.push(c),
+            }
+        }
+        records
+    }
+
+    #[sqlx::test]
+    async fn csv_export_round_trips_special_characters(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let text = "He said \"ho, ho\"\nand left";
+        add_quote(&router, "Santa, Claus", text).await;
+
+        let response = send(&router, get("/19/export?format=csv")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let records = parse_csv(&response.text());
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1][1], "Santa, Claus");
+        assert_eq!(records[1][2], text);
+    }
+
+    #[sqlx::test]
+    async fn ndjson_export_has_one_quote_per_line(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        add_quote(&router, "Santa", "Ho ho ho").await;
+        add_quote(&router, "Rudolph", "Shiny\nnose").await;
+
+        let response = send(&router, get("/19/export?format=ndjson")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers["content-type"], "application/x-ndjson");
+        let disposition = response.headers["content-disposition"].to_str().unwrap();
+        let today = Utc::now().format("%Y-%m-%d").to_string();
+        assert_eq!(
+            disposition,
+            format!("attachment; filename=\"quotes-{}.ndjson\"", today)
+        );
+        let quotes: Vec<JsonValue> = response
+            .text()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(quotes.len(), 2);
+        assert_eq!(quotes[1]["quote"], "Shiny\nnose");
+    }
+
+    // (フィールド, ルール)の組
+    type Violations = &'static [(&'static str, &'static str)];
+
+    #[test]
+    fn draft_validation_rules() {
+        let limits = DraftLimits {
+            max_author_chars: 5,
+            max_quote_chars: 8,
+        };
+        let cases: &[(&str, &str, Violations)] = &[
+            ("Santa", "Ho ho ho", &[]),
+            ("", "Ho ho ho", &[("author", "not_empty")]),
+            ("Santa", "   ", &[("quote", "not_empty")]),
+            ("Rudolph", "Ho ho ho", &[("author", "max_length")]),
+            ("Santa", "Ho ho ho!", &[("quote", "max_length")]),
+            (
+                "",
+                "Ho ho ho ho",
+                &[("author", "not_empty"), ("quote", "max_length")],
+            ),
+            // 文字数で数えるので、マルチバイト文字でも上限ちょうどなら通る
+            ("サンタさん", "🎅🎄🎁⛄🦌🍪🥛⭐", &[]),
+            (
+                "サンタクロース",
+                "🎅🎄🎁⛄🦌🍪🥛⭐🔔",
+                &[("author", "max_length"), ("quote", "max_length")],
+            ),
+        ];
+        for (author, quote, expected) in cases {
+            let draft = Draft {
+                author: author.to_string(),
+                quote: quote.to_string(),
+                created_at: None,
+            };
+            let actual: Vec<(&str, &str)> = match draft.validate(&limits) {
+                Ok(()) => Vec::new(),
+                Err(errors) => errors.iter().map(|e| (e.field, e.rule)).collect(),
+            };
+            assert_eq!(actual, *expected, "author={:?} quote={:?}", author, quote);
+        }
+    }
+
+    #[sqlx::test]
+    async fn invalid_drafts_list_each_failed_field(pool: sqlx::PgPool) {
+        let router = router_with(
+            pool.clone(),
+            AppConfig {
+                draft_limits: DraftLimits {
+                    max_author_chars: DEFAULT_MAX_AUTHOR_CHARS,
+                    max_quote_chars: 8,
+                },
+                ..AppConfig::default()
+            },
+        );
+        let draft = serde_json::json!({ "author": " ", "quote": "Ho ho ho!" });
+        let response = send(&router, post_json("/19/draft", draft.clone())).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.json()["error"]["code"], "validation_failed");
+        let errors = response.json()["error"]["details"].clone();
+        assert_eq!(errors[0]["field"], "author");
+        assert_eq!(errors[0]["rule"], "not_empty");
+        assert_eq!(errors[1]["field"], "quote");
+        assert_eq!(errors[1]["rule"], "max_length");
+        assert_eq!(errors[1]["message"], "quote must be at most 8 characters");
+
+        let quote = add_quote(&router, "Santa", "Ho ho").await;
+        let response = send(
+            &router,
+            request(
+                "PUT",
+                &format!("/19/undo/{}", quote["id"].as_str().unwrap()),
+                Some("application/json"),
+                Body::from(draft.to_string()),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(count_quotes(&pool).await, 1);
+    }
+
+    #[sqlx::test]
+    async fn applied_migrations_are_listed(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let response = send(&router, get("/19/migrations")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let migrations = response.json();
+        let migrations = migrations.as_array().unwrap();
+        assert_eq!(migrations[0]["version"], 1);
+        assert_eq!(migrations[0]["description"], "init");
+        assert!(migrations[0]["applied_on"].is_string());
+        let versions: Vec<i64> = migrations
+            .iter()
+            .map(|migration| migration["version"].as_i64().unwrap())
+            .collect();
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    async fn undo(router: &Router, id: &str, author: &str, quote: &str) -> TestResponse {
+        let draft = serde_json::json!({ "author": author, "quote": quote });
+        send(
+            router,
+            request(
+                "PUT",
+                &format!("/19/undo/{}", id),
+                Some("application/json"),
+                Body::from(draft.to_string()),
+            ),
+        )
+        .await
+    }
+
+    #[sqlx::test]
+    async fn history_lists_revisions_newest_first(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let quote = add_quote(&router, "Santa", "Ho").await;
+        let id = quote["id"].as_str().unwrap();
+        assert_eq!(
+            undo(&router, id, "Santa", "Ho ho").await.status,
+            StatusCode::OK
+        );
+        assert_eq!(
+            undo(&router, id, "Santa", "Ho ho ho").await.status,
+            StatusCode::OK
+        );
+
+        let response = send(&router, get(&format!("/19/history/{}", id))).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let history = response.json();
+        let versions: Vec<(i64, &str)> = history
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|revision| {
+                (
+                    revision["version"].as_i64().unwrap(),
+                    revision["quote"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(versions, [(3, "Ho ho ho"), (2, "Ho ho"), (1, "Ho")]);
+
+        let response = send(&router, get(&format!("/19/history/{}", Uuid::nil()))).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test]
+    async fn history_is_capped_per_quote(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let quote = add_quote(&router, "Santa", "v1").await;
+        let id = quote["id"].as_str().unwrap();
+        for version in 2..=52 {
+            let response = undo(&router, id, "Santa", &format!("v{}", version)).await;
+            assert_eq!(response.status, StatusCode::OK);
+        }
+
+        let history = send(&router, get(&format!("/19/history/{}", id)))
+            .await
+            .json();
+        let history = history.as_array().unwrap();
+        assert_eq!(history.len(), 50);
+        assert_eq!(history[0]["version"], 52);
+        assert_eq!(history[49]["version"], 3);
+    }
+
+    #[sqlx::test]
+    async fn removing_a_quote_can_keep_its_history(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let kept = add_quote(&router, "Santa", "Ho ho ho").await;
+        let kept = kept["id"].as_str().unwrap();
+        let dropped = add_quote(&router, "Grinch", "Bah").await;
+        let dropped = dropped["id"].as_str().unwrap();
+
+        let uri = format!("/19/remove/{}?keep_history=true", kept);
+        let response = send(&router, request("DELETE", &uri, None, Body::empty())).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let uri = format!("/19/remove/{}", dropped);
+        let response = send(&router, request("DELETE", &uri, None, Body::empty())).await;
+        assert_eq!(response.status, StatusCode::OK);
+
+        let response = send(&router, get(&format!("/19/history/{}", kept))).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json()[0]["quote"], "Ho ho ho");
+        let response = send(&router, get(&format!("/19/history/{}", dropped))).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    // quote_revisionsができる前からある引用も、移行時の内容が履歴になる
+    #[sqlx::test(migrations = false)]
+    async fn history_is_backfilled_for_existing_quotes(pool: sqlx::PgPool) {
+        let dir = std::env::temp_dir().join(format!("migrations-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let migrations = concat!(env!("CARGO_MANIFEST_DIR"), "/migrations");
+        for entry in std::fs::read_dir(migrations).unwrap() {
+            let name = entry.unwrap().file_name();
+            if name.to_str().unwrap() < "0007" {
+                std::fs::copy(
+                    std::path::Path::new(migrations).join(&name),
+                    dir.join(&name),
+                )
+                .unwrap();
+            }
+        }
+        let before_revisions = sqlx::migrate::Migrator::new(dir.clone()).await.unwrap();
+        before_revisions.run(&pool).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let id: Uuid = sqlx::query_scalar(
+            "INSERT INTO quotes (author, quote, version) VALUES ('Santa', 'Ho ho ho', 2) RETURNING id",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        sqlx::migrate!().run(&pool).await.unwrap();
+
+        let router = quote_router(pool);
+        let response = send(&router, get(&format!("/19/history/{}", id))).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let history = response.json();
+        assert_eq!(history.as_array().unwrap().len(), 1);
+        assert_eq!(history[0]["version"], 2);
+        assert_eq!(history[0]["quote"], "Ho ho ho");
+    }
+
+    #[sqlx::test]
+    async fn stuck_query_times_out_with_504(pool: sqlx::PgPool) {
+        let router = router_with(
+            pool.clone(),
+            AppConfig {
+                request_timeout: Duration::from_secs(1),
+                ..AppConfig::default()
+            },
+        );
+        let quote = add_quote(&router, "Santa", "Ho ho ho").await;
+
+        // 別のトランザクションでテーブルをロックして、ハンドラーのクエリを止める
+        let mut lock = pool.begin().await.unwrap();
+        sqlx::query("LOCK TABLE quotes IN ACCESS EXCLUSIVE MODE")
+            .execute(&mut *lock)
+            .await
+            .unwrap();
+        let uri = format!("/19/cite/{}", quote["id"].as_str().unwrap());
+        let response = send(&router, get(&uri)).await;
+        assert_eq!(response.status, StatusCode::GATEWAY_TIMEOUT);
+        lock.rollback().await.unwrap();
+
+        let response = send(&router, get(&uri)).await;
+        assert_eq!(response.status, StatusCode::OK);
+    }
+
+    #[sqlx::test]
+    async fn total_and_stats_are_zero_valued_when_empty(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let response = send(&router, get("/19/total")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json(), serde_json::json!({ "total": 0 }));
+
+        let response = send(&router, get("/19/stats")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json(),
+            serde_json::json!({ "authors": [], "earliest": null, "latest": null })
+        );
+    }
+
+    #[sqlx::test]
+    async fn stats_are_grouped_by_author(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let first = add_quote(&router, "Santa", "Ho").await;
+        add_quote(&router, "Rudolph", "Shiny").await;
+        add_quote(&router, "Comet", "Whoosh").await;
+        let last = add_quote(&router, "Santa", "Ho ho").await;
+
+        let response = send(&router, get("/19/total")).await;
+        assert_eq!(response.json()["total"], 4);
+
+        let stats = send(&router, get("/19/stats")).await.json();
+        let authors: Vec<(&str, i64)> = stats["authors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| (a["author"].as_str().unwrap(), a["count"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(authors, [("Santa", 2), ("Comet", 1), ("Rudolph", 1)]);
+        // 引用のcreated_atはミリ秒で切り捨てて出力される
+        let parse = |value: &JsonValue| {
+            use chrono::SubsecRound;
+            let timestamp = value.as_str().unwrap().parse::<DateTime<Utc>>().unwrap();
+            timestamp.trunc_subsecs(3)
+        };
+        assert_eq!(parse(&stats["earliest"]), parse(&first["created_at"]));
+        assert_eq!(parse(&stats["latest"]), parse(&last["created_at"]));
+
+        let stats = send(&router, get("/19/stats?min_count=2")).await.json();
+        assert_eq!(stats["authors"].as_array().unwrap().len(), 1);
+        assert_eq!(stats["authors"][0]["author"], "Santa");
+    }
+
+    async fn patch(router: &Router, id: &str, body: JsonValue) -> TestResponse {
+        send(
+            router,
+            request(
+                "PATCH",
+                &format!("/19/cite/{}", id),
+                Some("application/json"),
+                Body::from(body.to_string()),
+            ),
+        )
+        .await
+    }
+
+    #[sqlx::test]
+    async fn patch_updates_only_given_fields(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let quote = add_quote(&router, "Santa", "Ho ho hoo").await;
+        let id = quote["id"].as_str().unwrap();
+
+        let response = patch(&router, id, serde_json::json!({ "quote": "Ho ho ho" })).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let patched = response.json();
+        assert_eq!(patched["author"], "Santa");
+        assert_eq!(patched["quote"], "Ho ho ho");
+        assert_eq!(patched["version"], 2);
+
+        let response = patch(&router, id, serde_json::json!({ "author": "Rudolph" })).await;
+        assert_eq!(response.json()["quote"], "Ho ho ho");
+        assert_eq!(response.json()["version"], 3);
+
+        let history = send(&router, get(&format!("/19/history/{}", id)))
+            .await
+            .json();
+        assert_eq!(history[0]["version"], 3);
+        assert_eq!(history[0]["author"], "Rudolph");
+    }
+
+    #[sqlx::test]
+    async fn patch_rejects_empty_invalid_and_unknown(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let quote = add_quote(&router, "Santa", "Ho ho ho").await;
+        let id = quote["id"].as_str().unwrap();
+
+        let response = patch(&router, id, serde_json::json!({})).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        let response = patch(&router, id, serde_json::json!({ "author": " " })).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.json()["error"]["details"][0]["field"], "author");
+        let unknown = Uuid::nil().to_string();
+        let response = patch(&router, &unknown, serde_json::json!({ "quote": "Hi" })).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+        let current = send(&router, get(&format!("/19/cite/{}", id))).await.json();
+        assert_eq!(current["version"], 1);
+    }
+
+    fn with_peer(mut request: Request, peer: &str) -> Request {
+        request.extensions_mut().insert(axum::extract::ConnectInfo(
+            format!("{}:443", peer).parse::<SocketAddr>().unwrap(),
+        ));
+        request
+    }
+
+    fn from_peer(peer: &str, forwarded_for: Option<&str>) -> Request {
+        let mut request = with_peer(get("/19/draft"), peer);
+        if let Some(forwarded_for) = forwarded_for {
+            request
+                .headers_mut()
+                .insert("X-Forwarded-For", forwarded_for.parse().unwrap());
+        }
+        request
+    }
+
+    fn draft_from(peer: &str, quote: &str) -> Request {
+        let draft = serde_json::json!({ "author": "Santa", "quote": quote });
+        with_peer(post_json("/19/draft", draft), peer)
+    }
+
+    #[sqlx::test]
+    async fn exhausted_write_limit_returns_too_many_requests(pool: sqlx::PgPool) {
+        let router = router_with(
+            pool,
+            AppConfig {
+                write_bucket_size: 2,
+                write_refill_interval: Duration::from_secs(60),
+                ..AppConfig::default()
+            },
+        );
+
+        for quote in ["one", "two"] {
+            let response = send(&router, draft_from("203.0.113.7", quote)).await;
+            assert_eq!(response.status, StatusCode::CREATED);
+        }
+        let limited = send(&router, draft_from("203.0.113.7", "three")).await;
+        assert_eq!(limited.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(limited.headers["retry-after"], "60");
+
+        // 読み取りと他のクライアントの書き込みは制限されない
+        assert_eq!(send(&router, get("/19/list")).await.status, StatusCode::OK);
+        let other = send(&router, draft_from("198.51.100.1", "four")).await;
+        assert_eq!(other.status, StatusCode::CREATED);
+    }
+
+    #[test]
+    fn client_id_ignores_forwarded_for_from_untrusted_peers() {
+        let request = from_peer("203.0.113.7", Some("198.51.100.1"));
+        assert_eq!(client_id(&request, &[]), "203.0.113.7");
+        let trusted = ["10.0.0.1".parse().unwrap()];
+        assert_eq!(client_id(&request, &trusted), "203.0.113.7");
+    }
+
+    #[test]
+    fn client_id_takes_right_most_untrusted_hop() {
+        let trusted = ["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+        // 先頭はクライアントが自由に書けるので使わない
+        let request = from_peer("10.0.0.1", Some("1.2.3.4, 198.51.100.9, 10.0.0.2"));
+        assert_eq!(client_id(&request, &trusted), "198.51.100.9");
+        // 全部が信頼できるプロキシなら一番左のプロキシを使う
+        let request = from_peer("10.0.0.1", Some("10.0.0.2"));
+        assert_eq!(client_id(&request, &trusted), "10.0.0.2");
+        let request = from_peer("10.0.0.1", Some("not-an-ip, 10.0.0.2"));
+        assert_eq!(client_id(&request, &trusted), "10.0.0.2");
+        let request = from_peer("10.0.0.1", None);
+        assert_eq!(client_id(&request, &trusted), "10.0.0.1");
+    }
+
+    #[test]
+    fn invalid_trusted_proxies_are_rejected() {
+        assert_eq!(
+            parse_trusted_proxies("10.0.0.1, ::1").unwrap(),
+            [
+                "10.0.0.1".parse::<IpAddr>().unwrap(),
+                "::1".parse::<IpAddr>().unwrap()
+            ]
+        );
+        assert!(parse_trusted_proxies("10.0.0.1, proxy").is_err());
+    }
+
+    #[tokio::test]
+    async fn write_limiter_evicts_least_recently_used_bucket() {
+        let mut limiter = WriteLimiter::new(1, Duration::from_secs(60));
+        limiter.max_buckets = 2;
+        assert!(limiter.try_acquire("a"));
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(limiter.try_acquire("b"));
+        // どちらも使い切っているので、満タンのバケットとしては捨てられない
+        assert!(limiter.try_acquire("c"));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert!(!buckets.contains_key("a"));
+        assert!(buckets.contains_key("b") && buckets.contains_key("c"));
+    }
+
+    #[sqlx::test]
+    async fn by_author_pages_through_one_author(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=4 {
+            add_quote(&router, "Mrs. Claus & Co", &format!("quote {}", i)).await;
+            add_quote(&router, "Santa", &format!("other {}", i)).await;
+        }
+
+        let first = list_page(&router, "/19/by-author/Mrs.%20Claus%20%26%20Co").await;
+        assert_eq!(quote_texts(&first), ["quote 1", "quote 2", "quote 3"]);
+        assert_eq!(first["total"], 4);
+        let token = first["next_token"].as_str().unwrap();
+
+        let second = list_page(
+            &router,
+            &format!("/19/by-author/Mrs.%20Claus%20%26%20Co?token={}", token),
+        )
+        .await;
+        assert_eq!(quote_texts(&second), ["quote 4"]);
+        assert_eq!(second["page"], 2);
+        assert!(second["next_token"].is_null());
+
+        let empty = list_page(&router, "/19/by-author/Nobody").await;
+        assert_eq!(empty["quotes"], serde_json::json!([]));
+        assert!(empty["next_token"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn missing_quote_error_shape(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let id = Uuid::nil();
+        let draft = serde_json::json!({ "author": "Santa", "quote": "Ho ho ho" }).to_string();
+        let requests = [
+            get(&format!("/19/cite/{}", id)),
+            request("DELETE", &format!("/19/remove/{}", id), None, Body::empty()),
+            request(
+                "PUT",
+                &format!("/19/undo/{}", id),
+                Some("application/json"),
+                Body::from(draft),
+            ),
+        ];
+        for request in requests {
+            let uri = request.uri().to_string();
+            let response = send(&router, request).await;
+            assert_eq!(response.status, StatusCode::NOT_FOUND, "{}", uri);
+            assert_eq!(
+                response.json(),
+                serde_json::json!({ "error": { "code": "not_found", "message": "Quote not found" } }),
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[sqlx::test]
+    async fn list_rejects_pages_beyond_max_page(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        for i in 1..=4 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+        let tokens = PaginationTokens {
+            ttl: Duration::from_secs(60),
+            retention: Duration::from_secs(60),
+            max_tokens: 10,
+            deterministic_counter: None,
+        };
+        tokens
+            .insert(&pool, "deep", &pagination_state(MAX_PAGE + 1))
+            .await
+            .unwrap();
+        // カーソルのないトークンはOFFSETで読み飛ばすので、先頭より前のカーソルを持たせる
+        let last = PaginationState {
+            cursor_value: Some("1970-01-01T00:00:00Z".to_string()),
+            cursor_id: Some(Uuid::nil()),
+            ..pagination_state(MAX_PAGE)
+        };
+        tokens.insert(&pool, "last", &last).await.unwrap();
+
+        let response = send(&router, get("/19/list?token=deep")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.json()["error"]["message"],
+            format!("page must not exceed {}", MAX_PAGE)
+        );
+
+        // 上限のページからは次のトークンを発行しない
+        let last = list_page(&router, "/19/list?token=last").await;
+        assert_eq!(last["page"], MAX_PAGE);
+        assert_eq!(quote_texts(&last).len(), 3);
+        assert!(last["next_token"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn default_page_size_is_configurable(pool: sqlx::PgPool) {
+        let router = router_with(
+            pool,
+            AppConfig {
+                default_per_page: 2,
+                ..AppConfig::default()
+            },
+        );
+        for i in 1..=3 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+        let first = list_page(&router, "/19/list").await;
+        assert_eq!(quote_texts(&first), ["quote 1", "quote 2"]);
+        let first = list_page(&router, "/19/list?limit=3").await;
+        assert_eq!(quote_texts(&first).len(), 3);
+    }
+
+    #[sqlx::test]
+    async fn tokens_are_bound_to_their_endpoint(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=4 {
+            add_quote(&router, "Santa", &format!("jolly quote {}", i)).await;
+        }
+
+        let fts = list_page(&router, "/19/fts?q=jolly&limit=1").await;
+        let token = fts["next_token"].as_str().unwrap();
+        let response = send(&router, get(&format!("/19/list?token={}", token))).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.json()["error"]["message"],
+            "Token was issued by a different endpoint"
+        );
+
+        let search = list_page(&router, "/19/search?q=jolly&limit=1").await;
+        let token = search["next_token"].as_str().unwrap();
+        let response = send(
+            &router,
+            get(&format!("/19/by-author/Santa?token={}", token)),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+        let list = list_page(&router, "/19/list?limit=1").await;
+        let token = list["next_token"].as_str().unwrap();
+        let response = send(&router, get(&format!("/19/fts?token={}", token))).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn fts_ranks_highlights_and_pages(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        add_quote(&router, "Santa", "Cookies are nice").await;
+        add_quote(
+            &router,
+            "Rudolph",
+            "Cookies and more cookies, always cookies",
+        )
+        .await;
+        add_quote(&router, "Elf", "Milk is fine").await;
+
+        let first = list_page(&router, "/19/fts?q=cookie&limit=1").await;
+        let results = first["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["author"], "Rudolph");
+        assert!(results[0]["headline"]
+            .as_str()
+            .unwrap()
+            .contains("<mark>Cookies</mark>"));
+        let token = first["next_token"].as_str().unwrap();
+
+        let second = list_page(&router, &format!("/19/fts?token={}", token)).await;
+        let results = second["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["author"], "Santa");
+        assert_eq!(second["page"], 2);
+        assert!(second["next_token"].is_null());
+
+        // 空のクエリやストップワードだけのクエリは400
+        for uri in ["/19/fts", "/19/fts?q=", "/19/fts?q=the"] {
+            let response = send(&router, get(uri)).await;
+            assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[sqlx::test]
+    async fn concurrent_deletes_remove_once(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        let quote = add_quote(&router, "Santa", "Ho ho ho").await;
+        let uri = format!("/19/remove/{}", quote["id"].as_str().unwrap());
+
+        let (first, second) = tokio::join!(
+            send(&router, request("DELETE", &uri, None, Body::empty())),
+            send(&router, request("DELETE", &uri, None, Body::empty())),
+        );
+        let mut statuses = [first.status, second.status];
+        statuses.sort();
+        assert_eq!(statuses, [StatusCode::OK, StatusCode::NOT_FOUND]);
+        let removed = if first.status == StatusCode::OK {
+            first
+        } else {
+            second
+        };
+        assert_eq!(removed.json()["quote"], "Ho ho ho");
+        assert_eq!(count_quotes(&pool).await, 0);
+    }
+
+    #[sqlx::test]
+    async fn reused_idempotency_key_with_other_payload_is_rejected(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        let draft = serde_json::json!({ "author": "Santa", "quote": "Ho ho ho" });
+        let first = send(&router, draft_with_key("retry-1", &draft)).await;
+        assert_eq!(first.status, StatusCode::CREATED);
+
+        let other = serde_json::json!({ "author": "Santa", "quote": "Ho ho" });
+        let response = send(&router, draft_with_key("retry-1", &other)).await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.json()["error"]["code"], "idempotency_key_reused");
+        assert_eq!(count_quotes(&pool).await, 1);
+
+        // 24時間を過ぎたキーは使われていないものとして扱う
+        sqlx::query("UPDATE idempotency_keys SET created_at = now() - interval '25 hours'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let response = send(&router, draft_with_key("retry-1", &other)).await;
+        assert_eq!(response.status, StatusCode::CREATED);
+    }
+
+    #[sqlx::test]
+    async fn concurrent_requests_with_one_key_create_one_quote(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        let draft = serde_json::json!({ "author": "Santa", "quote": "Ho ho ho" });
+        let (first, second) = tokio::join!(
+            send(&router, draft_with_key("race", &draft)),
+            send(&router, draft_with_key("race", &draft)),
+        );
+        let mut statuses = [first.status, second.status];
+        statuses.sort();
+        assert_eq!(statuses, [StatusCode::OK, StatusCode::CREATED]);
+        assert_eq!(first.json()["id"], second.json()["id"]);
+        assert_eq!(count_quotes(&pool).await, 1);
+    }
+
+    #[sqlx::test]
+    async fn authors_are_grouped_filtered_and_paged(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let response = send(&router, get("/19/authors")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json(), serde_json::json!([]));
+
+        for (author, quote) in [
+            ("Santa", "Ho"),
+            ("Rudolph", "Shiny"),
+            ("Santa", "Ho ho"),
+            ("Sam_elf", "Hi"),
+            ("Samuel", "Hello"),
+        ] {
+            add_quote(&router, author, quote).await;
+        }
+
+        let authors = send(&router, get("/19/authors")).await.json();
+        let names: Vec<&str> = authors
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|a| a["author"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["Rudolph", "Sam_elf", "Samuel", "Santa"]);
+        assert_eq!(authors[3]["count"], 2);
+        assert!(authors[3]["latest"].is_string());
+
+        // 前方一致は大文字小文字を無視し、_はワイルドカードにならない
+        let authors = send(&router, get("/19/authors?prefix=SAM")).await.json();
+        assert_eq!(authors.as_array().unwrap().len(), 2);
+        let authors = send(&router, get("/19/authors?prefix=sam_")).await.json();
+        assert_eq!(authors.as_array().unwrap().len(), 1);
+        assert_eq!(authors[0]["author"], "Sam_elf");
+
+        let page = send(&router, get("/19/authors?limit=2&after=Sam_elf"))
+            .await
+            .json();
+        assert_eq!(page[0]["author"], "Samuel");
+        assert_eq!(page[1]["author"], "Santa");
+        assert_eq!(page.as_array().unwrap().len(), 2);
+
+        let response = send(&router, get("/19/authors?limit=0")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[sqlx::test]
+    async fn page_past_the_end_has_no_prev(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=4 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+
+        let beyond = list_page(&router, "/19/list?page=5").await;
+        assert!(quote_texts(&beyond).is_empty());
+        assert_eq!(beyond["total_pages"], 2);
+        assert_eq!(beyond["has_prev"], false);
+        assert!(beyond["prev_token"].is_null());
+        assert!(beyond["next_token"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn page_number_and_token_each_select_a_page(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=7 {
+            add_quote(&router, "Santa", &format!("quote {}", i)).await;
+        }
+
+        let by_number = list_page(&router, "/19/list?page=2").await;
+        assert_eq!(quote_texts(&by_number), ["quote 4", "quote 5", "quote 6"]);
+        assert_eq!(by_number["page"], 2);
+        let first = list_page(&router, "/19/list").await;
+        let token = first["next_token"].as_str().unwrap().to_string();
+        let by_token = list_page(&router, &format!("/19/list?token={}", token)).await;
+        assert_eq!(quote_texts(&by_token), quote_texts(&by_number));
+
+        // 両方あればトークンを優先する
+        let first = list_page(&router, "/19/list").await;
+        let token = first["next_token"].as_str().unwrap();
+        let both = list_page(&router, &format!("/19/list?page=3&token={}", token)).await;
+        assert_eq!(both["page"], 2);
+
+        for uri in [
+            "/19/list?page=0",
+            "/19/list?page=-1",
+            "/19/list?page=two",
+            "/19/list?page=1000000000",
+        ] {
+            let response = send(&router, get(uri)).await;
+            assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[sqlx::test]
+    async fn totals_respect_the_listing_filters(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        for i in 1..=5 {
+            add_quote(&router, "Santa", &format!("jolly {}", i)).await;
+        }
+        add_quote(&router, "Rudolph", "jolly nose").await;
+        add_quote(&router, "Rudolph", "shiny").await;
+
+        let list = list_page(&router, "/19/list").await;
+        assert_eq!(list["total"], 7);
+        assert_eq!(list["total_pages"], 3);
+        let search = list_page(&router, "/19/search?q=jolly").await;
+        assert_eq!(search["total"], 6);
+        assert_eq!(search["total_pages"], 2);
+        let by_author = list_page(&router, "/19/by-author/Rudolph").await;
+        assert_eq!(by_author["total"], 2);
+        assert_eq!(by_author["total_pages"], 1);
+    }
+
+    #[sqlx::test]
+    async fn created_at_has_millisecond_precision(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        // DBにはマイクロ秒まで保存されていても、出力はミリ秒で切り捨てる
+        sqlx::query(
+            "INSERT INTO quotes (author, quote, created_at) \
+             VALUES ('Santa', 'Ho', '2024-12-24T12:34:56.789123Z')",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let list = list_page(&router, "/19/list").await;
+        assert_eq!(list["quotes"][0]["created_at"], "2024-12-24T12:34:56.789Z");
+
+        let created = add_quote(&router, "Santa", "Ho ho").await;
+        let created_at = created["created_at"].as_str().unwrap();
+        assert_eq!(created_at.len(), "2024-12-24T12:34:56.789Z".len());
+        assert!(created_at.ends_with('Z'));
+    }
+
+    #[sqlx::test]
+    async fn imported_created_at_is_kept_and_ordered(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        add_quote(&router, "Santa", "new").await;
+        let draft = serde_json::json!({
+            "author": "Santa",
+            "quote": "old",
+            "created_at": "2020-12-24T00:00:00.5+09:00",
+        });
+        let response = send(&router, post_json("/19/draft", draft)).await;
+        assert_eq!(response.status, StatusCode::CREATED);
+        assert_eq!(response.json()["created_at"], "2020-12-23T15:00:00.500Z");
+
+        let batch = serde_json::json!([
+            { "author": "Elf", "quote": "older", "created_at": "2019-01-01T00:00:00Z" },
+        ]);
+        let response = send(&router, post_json("/19/draft/batch", batch)).await;
+        assert_eq!(response.status, StatusCode::CREATED);
+
+        let list = list_page(&router, "/19/list").await;
+        assert_eq!(quote_texts(&list), ["older", "old", "new"]);
+
+        let future = (Utc::now() + chrono::Duration::minutes(5)).to_rfc3339();
+        let draft = serde_json::json!({ "author": "Santa", "quote": "soon", "created_at": future });
+        let response = send(&router, post_json("/19/draft", draft)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.json()["error"]["details"][0]["rule"], "in_future");
+        let draft = serde_json::json!({
+            "author": "Santa",
+            "quote": "ancient",
+            "created_at": "1969-12-31T23:59:59Z",
+        });
+        let response = send(&router, post_json("/19/draft", draft)).await;
+        assert_eq!(response.json()["error"]["details"][0]["rule"], "too_old");
+    }
+
+    #[sqlx::test]
+    async fn v7_ids_sort_in_creation_order(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let mut created = Vec::new();
+        for i in 1..=4 {
+            let quote = add_quote(&router, "Santa", &format!("quote {}", i)).await;
+            let id = Uuid::parse_str(quote["id"].as_str().unwrap()).unwrap();
+            assert_eq!(id.get_version_num(), 7);
+            created.push(id);
+        }
+        let batch = serde_json::json!([{ "author": "Elf", "quote": "quote 5" }]);
+        let response = send(&router, post_json("/19/draft/batch", batch)).await;
+        let id = Uuid::parse_str(response.json()[0]["id"].as_str().unwrap()).unwrap();
+        assert_eq!(id.get_version_num(), 7);
+        created.push(id);
+        let mut sorted = created.clone();
+        sorted.sort();
+        assert_eq!(sorted, created);
+
+        let first = list_page(&router, "/19/list?sort=id").await;
+        assert_eq!(quote_texts(&first), ["quote 1", "quote 2", "quote 3"]);
+        let token = first["next_token"].as_str().unwrap();
+        let second = list_page(&router, &format!("/19/list?token={}", token)).await;
+        assert_eq!(quote_texts(&second), ["quote 4", "quote 5"]);
+
+        let desc = list_page(&router, "/19/list?sort=id&order=desc").await;
+        assert_eq!(quote_texts(&desc), ["quote 5", "quote 4", "quote 3"]);
+    }
+
+    #[sqlx::test]
+    async fn raw_cite_returns_only_the_text(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let quote = add_quote(&router, "Santa", "\"Ho\", ho & ho").await;
+        let id = quote["id"].as_str().unwrap();
+
+        let response = send(&router, get(&format!("/19/cite/{}/raw", id))).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers[CONTENT_TYPE], "text/plain; charset=utf-8");
+        assert_eq!(response.text(), "\"Ho\", ho & ho");
+
+        let response = send(&router, get(&format!("/19/cite/{}/raw", Uuid::nil()))).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test]
+    async fn concurrent_undos_both_increment_version(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let created = add_quote(&router, "Santa", "Ho ho ho").await;
+        let id = created["id"].as_str().unwrap();
+
+        let (first, second) = tokio::join!(
+            undo(&router, id, "Santa", "first"),
+            undo(&router, id, "Santa", "second"),
+        );
+        assert_eq!(first.status, StatusCode::OK);
+        assert_eq!(second.status, StatusCode::OK);
+        let mut versions = [
+            first.json()["version"].clone(),
+            second.json()["version"].clone(),
+        ];
+        versions.sort_by_key(|v| v.as_i64());
+        assert_eq!(versions, [serde_json::json!(2), serde_json::json!(3)]);
+
+        // 最後に書き込まれた方が保存されている
+        let last = if first.json()["version"] == 3 {
+            first
+        } else {
+            second
+        };
+        let current = send(&router, get(&format!("/19/cite/{}", id))).await.json();
+        assert_eq!(current["version"], 3);
+        assert_eq!(current["quote"], last.json()["quote"]);
+
+        let response = undo(&router, &Uuid::new_v4().to_string(), "Santa", "gone").await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test]
+    async fn write_limit_covers_undo_and_remove(pool: sqlx::PgPool) {
+        let router = router_with(
+            pool,
+            AppConfig {
+                write_bucket_size: 1,
+                write_refill_interval: Duration::from_secs(60),
+                ..AppConfig::default()
+            },
+        );
+        let created = send(&router, draft_from("203.0.113.7", "Ho ho ho")).await;
+        assert_eq!(created.status, StatusCode::CREATED);
+        let id = created.json()["id"].as_str().unwrap().to_string();
+
+        let draft = serde_json::json!({ "author": "Santa", "quote": "Ho" });
+        let undo = request(
+            "PUT",
+            &format!("/19/undo/{}", id),
+            Some("application/json"),
+            Body::from(draft.to_string()),
+        );
+        let remove = request("DELETE", &format!("/19/remove/{}", id), None, Body::empty());
+        for request in [undo, remove] {
+            let response = send(&router, with_peer(request, "203.0.113.7")).await;
+            assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(response.json()["error"]["code"], "rate_limited");
+        }
+
+        let cite = with_peer(get(&format!("/19/cite/{}", id)), "203.0.113.7");
+        assert_eq!(send(&router, cite).await.status, StatusCode::OK);
+    }
+
+    fn with_if_match(method: &str, uri: &str, body: JsonValue, version: &str) -> Request {
+        let mut request = request(
+            method,
+            uri,
+            Some("application/json"),
+            Body::from(body.to_string()),
+        );
+        request
+            .headers_mut()
+            .insert(header::IF_MATCH, version.parse().unwrap());
+        request
+    }
+
+    #[sqlx::test]
+    async fn if_match_guards_undo_and_patch(pool: sqlx::PgPool) {
+        let router = quote_router(pool);
+        let quote = add_quote(&router, "Santa", "Ho ho ho").await;
+        let id = quote["id"].as_str().unwrap();
+        let cite = format!("/19/cite/{}", id);
+        let undo = format!("/19/undo/{}", id);
+        let draft = serde_json::json!({ "author": "Santa", "quote": "Ho" });
+
+        let response = send(&router, with_if_match("PUT", &undo, draft.clone(), "1")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json()["version"], 2);
+
+        // 古いバージョンを指定した更新は409で、引用は変わらない
+        let response = send(&router, with_if_match("PUT", &undo, draft.clone(), "1")).await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+        assert_eq!(response.json()["error"]["code"], "conflict");
+        let body = serde_json::json!({ "quote": "Hi" });
+        let response = send(
+            &router,
+            with_if_match("PATCH", &cite, body.clone(), "\"1\""),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+        assert_eq!(send(&router, get(&cite)).await.json()["version"], 2);
+
+        let response = send(
+            &router,
+            with_if_match("PATCH", &cite, body.clone(), "\"2\""),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json()["version"], 3);
+
+        let response = send(
+            &router,
+            with_if_match("PATCH", &cite, body.clone(), "latest"),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+        let missing = format!("/19/undo/{}", Uuid::nil());
+        let response = send(&router, with_if_match("PUT", &missing, draft, "1")).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    #[sqlx::test]
+    async fn list_filters_by_created_at(pool: sqlx::PgPool) {
+        let router = quote_router(pool.clone());
+        for day in 1..=6 {
+            sqlx::query("INSERT INTO quotes (author, quote, created_at) VALUES ($1, $2, $3)")
+                .bind("Santa")
+                .bind(format!("day {}", day))
+                .bind(
+                    format!("2024-12-0{}T00:00:00Z", day)
+                        .parse::<DateTime<Utc>>()
+                        .unwrap(),
+                )
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        // 境界の日時ちょうどのものは含まない
+        let uri = "/19/list?after=2024-12-01T00:00:00Z&before=2024-12-06T00:00:00Z";
+        let first = list_page(&router, uri).await;
+        assert_eq!(quote_texts(&first), ["day 2", "day 3", "day 4"]);
+        assert_eq!(first["total"], 4);
+
+        // 次のページのトークンにも範囲が引き継がれる
+        let token = first["next_token"].as_str().unwrap();
+        let second = list_page(&router, &format!("/19/list?token={}", token)).await;
+        assert_eq!(quote_texts(&second), ["day 5"]);
+        assert!(second["next_token"].is_null());
+
+        let response = send(&router, get("/19/list?after=yesterday")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.json()["error"]["message"],
+            "after must be an RFC 3339 timestamp"
+        );
+    }
+}
@@ -0,0 +1,1601 @@
+use crate::{error::ApiErrorResponse, payload_too_large, wants_json, AppState};
+use axum::{
+    extract::{
+        multipart::MultipartError, rejection::QueryRejection, DefaultBodyLimit, FromRequest, Json,
+        Multipart, Path, Query, Request, State,
+    },
+    http::{
+        header::{self, HeaderMap, CONTENT_TYPE},
+        HeaderValue, StatusCode,
+    },
+    middleware,
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use futures::StreamExt;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::atomic::Ordering};
+use utoipa::OpenApi;
+
+pub(crate) const DEFAULT_PRESENT_COLORS: [&str; 3] = ["red", "blue", "purple"];
+
+const DEFAULT_ORNAMENT_COUNT: usize = 7;
+const MAX_ORNAMENT_COUNT: usize = 200;
+
+pub(crate) const DEFAULT_LOCKFILE_MAX_BYTES: usize = 2 * 1024 * 1024;
+pub(crate) const DEFAULT_LOCKFILE_MAX_PACKAGES: usize = 2000;
+// multipartの境界や他のフィールドの分、ボディ全体にはlockfileより少し余裕を持たせる
+const LOCKFILE_MULTIPART_OVERHEAD_BYTES: usize = 64 * 1024;
+
+#[derive(Deserialize)]
+struct StarQuery {
+    #[serde(default)]
+    poll: bool,
+    lit: Option<String>,
+}
+
+// pollを指定しなければ、これまでと同じマークアップを返す
+fn render_star(lit: bool, poll: bool) -> Html<String> {
+    let class = if lit { " class=\"lit\"" } else { "" };
+    let poll = if poll {
+        " hx-get=\"/23/star\" hx-trigger=\"every 5s\" hx-swap=\"outerHTML\""
+    } else {
+        ""
+    };
+    Html(format!("<div id=\"star\"{}{}></div>", class, poll))
+}
+
+#[utoipa::path(
+    get,
+    path = "/23/star",
+    tag = "decorations",
+    params(("poll" = Option<bool>, Query, description = "Keep polling for changes")),
+    responses((status = 200, description = "The star fragment", body = String, content_type = "text/html")),
+)]
+async fn get_light_star(
+    State(state): State<AppState>,
+    Query(query): Query<StarQuery>,
+) -> Html<String> {
+    render_star(state.star_lit.load(Ordering::Relaxed), query.poll)
+}
+
+#[utoipa::path(
+    post,
+    path = "/23/star",
+    tag = "decorations",
+    params(("poll" = Option<bool>, Query, description = "Keep polling for changes"), ("lit" = String, Query, description = "true or false")),
+    responses((status = 200, description = "The star fragment", body = String, content_type = "text/html"), (status = 400, description = "Invalid lit", body = String, content_type = "text/plain")),
+)]
+async fn set_star(
+    State(state): State<AppState>,
+    Query(query): Query<StarQuery>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let lit = match query.lit.as_deref() {
+        Some("true") => true,
+        Some("false") => false,
+        _ => return Err((StatusCode::BAD_REQUEST, "Invalid lit".to_string())),
+    };
+    state.star_lit.store(lit, Ordering::Relaxed);
+    Ok(render_star(lit, query.poll))
+}
+
+#[utoipa::path(
+    post,
+    path = "/23/star/toggle",
+    tag = "decorations",
+    params(("poll" = Option<bool>, Query, description = "Keep polling for changes")),
+    responses((status = 200, description = "The star fragment", body = String, content_type = "text/html")),
+)]
+async fn toggle_star(
+    State(state): State<AppState>,
+    Query(query): Query<StarQuery>,
+) -> Html<String> {
+    // fetch_xorは変更前の値を返すので反転させる
+    let lit = !state.star_lit.fetch_xor(true, Ordering::Relaxed);
+    render_star(lit, query.poll)
+}
+
+#[derive(Serialize)]
+struct PresentInfo {
+    color: String,
+    next: String,
+}
+
+#[derive(Serialize)]
+struct PresentCycle {
+    colors: Vec<String>,
+}
+
+pub fn parse_present_colors(value: &str) -> Vec<String> {
+    let mut colors: Vec<String> = Vec::new();
+    for color in value.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        if !colors.iter().any(|c| c == color) {
+            colors.push(color.to_string());
+        }
+    }
+    colors
+}
+
+// リストの末尾の次は先頭に戻る
+fn next_present_color<'a>(colors: &'a [String], color: &str) -> Option<&'a str> {
+    let i = colors.iter().position(|c| c == color)?;
+    Some(&colors[(i + 1) % colors.len()])
+}
+
+// "#ff8800"または"hex-ff8800"の形式で、ちょうど6桁の16進数だけを受け付ける
+fn parse_hex_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color
+        .strip_prefix('#')
+        .or_else(|| color.strip_prefix("hex-"))?;
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+fn format_hex_color(rgb: [u8; 3]) -> String {
+    format!("{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+// RGBをHSLに変換して色相だけを回し、RGBに戻す
+fn rotate_hue(rgb: [u8; 3], degrees: f64) -> [u8; 3] {
+    let [r, g, b] = rgb.map(|c| c as f64 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    // 無彩色には色相がないのでそのまま
+    if chroma == 0.0 {
+        return rgb;
+    }
+    let lightness = (max + min) / 2.0;
+    let hue = if max == r {
+        60.0 * ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+
+    let hue = (hue + degrees).rem_euclid(360.0);
+    let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    [r, g, b].map(|c| ((c + m) * 255.0).round() as u8)
+}
+
+// attributesはエスケープ済みのclassやstyle
+fn render_present_div(attributes: &str, next: &str) -> String {
+    format!(
+        "<div {} hx-get=\"/23/present/{}\" hx-swap=\"outerHTML\">
+                    <div class=\"ribbon\"></div>
+                    <div class=\"ribbon\"></div>
+                    <div class=\"ribbon\"></div>
+                    <div class=\"ribbon\"></div>
+                </div>",
+        attributes,
+        html_escape::encode_double_quoted_attribute(
+            &utf8_percent_encode(next, PATH_SEGMENT).to_string()
+        ),
+    )
+}
+
+fn render_present(color: &str, next: &str) -> String {
+    render_present_div(
+        &format!(
+            "class=\"present {}\"",
+            html_escape::encode_double_quoted_attribute(color)
+        ),
+        next,
+    )
+}
+
+fn render_hex_present(rgb: [u8; 3], next: &str) -> String {
+    render_present_div(
+        &format!(
+            "class=\"present\" style=\"background-color:#{}\"",
+            format_hex_color(rgb)
+        ),
+        next,
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/23/present/{color}",
+    tag = "decorations",
+    params(("color" = String, Path, description = "A configured color name or hex (#rrggbb / hex-rrggbb)")),
+    responses(
+        (status = 200, description = "The present fragment (JSON with Accept: application/json)", body = String, content_type = "text/html"),
+        (status = 418, description = "Unknown color"),
+    ),
+)]
+async fn get_present(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(color): Path<String>,
+) -> Response {
+    // 名前付きの色が優先で、それ以外は16進数の色として色相を120°ずつ回す
+    let (next, html) = if let Some(next) = next_present_color(&state.present_colors, &color) {
+        (next.to_string(), render_present(&color, next))
+    } else if let Some(rgb) = parse_hex_color(&color) {
+        let next = format!("hex-{}", format_hex_color(rotate_hue(rgb, 120.0)));
+        let html = render_hex_present(rgb, &next);
+        (next, html)
+    } else {
+        return (StatusCode::IM_A_TEAPOT, Html("")).into_response();
+    };
+
+    // HTMX以外のクライアント向けにJSONでも返せるようにする
+    if wants_json(&headers) {
+        return Json(PresentInfo { color, next }).into_response();
+    }
+
+    Html(html).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/23/presents",
+    tag = "decorations",
+    responses((status = 200, description = "The present color cycle", content_type = "application/json")),
+)]
+async fn list_present_colors(State(state): State<AppState>) -> Json<PresentCycle> {
+    Json(PresentCycle {
+        colors: state.present_colors.to_vec(),
+    })
+}
+
+#[derive(Serialize)]
+struct OrnamentInfo {
+    id: String,
+    state: String,
+    next_state: &'static str,
+}
+
+// パスの1セグメントとして安全なように、非予約文字以外はすべてパーセントエンコードする
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+#[derive(Deserialize)]
+struct OrnamentQuery {
+    delay: Option<String>,
+}
+
+// "500ms"や"3s"のような形式で、50msから60sまでを受け付ける
+fn is_valid_ornament_delay(delay: &str) -> bool {
+    let (digits, unit_ms) = if let Some(digits) = delay.strip_suffix("ms") {
+        (digits, 1)
+    } else if let Some(digits) = delay.strip_suffix('s') {
+        (digits, 1000)
+    } else {
+        return false;
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(unit_ms))
+        .is_some_and(|ms| (50..=60_000).contains(&ms))
+}
+
+#[utoipa::path(
+    get,
+    path = "/23/ornament/{state}/{n}",
+    tag = "decorations",
+    params(
+        ("state" = String, Path, description = "on or off"),
+        ("n" = String, Path, description = "Ornament id"),
+        ("delay" = Option<String>, Query, description = "htmx delay between 50ms and 60s"),
+    ),
+    responses(
+        (status = 200, description = "The ornament fragment (JSON with Accept: application/json)", body = String, content_type = "text/html"),
+        (status = 400, description = "Invalid ornament id or delay", body = String, content_type = "text/plain"),
+        (status = 418, description = "Invalid state"),
+    ),
+)]
+async fn get_ornament(
+    headers: HeaderMap,
+    Path((state, n)): Path<(String, String)>,
+    Query(query): Query<OrnamentQuery>,
+) -> Response {
+    if n.chars().any(char::is_control) {
+        return (StatusCode::BAD_REQUEST, "Invalid ornament id").into_response();
+    }
+    if let Some(delay) = &query.delay {
+        if !is_valid_ornament_delay(delay) {
+            return (StatusCode::BAD_REQUEST, "Invalid delay").into_response();
+        }
+    }
+    if wants_json(&headers) {
+        let next_state = match state.as_str() {
+            "on" => "off",
+            "off" => "on",
+            _ => return StatusCode::IM_A_TEAPOT.into_response(),
+        };
+        return Json(OrnamentInfo {
+            id: n,
+            state,
+            next_state,
+        })
+        .into_response();
+    }
+
+    match render_ornament(&state, &n, query.delay.as_deref()) {
+        Some(ornament) => Html(ornament).into_response(),
+        None => (StatusCode::IM_A_TEAPOT, Html("")).into_response(),
+    }
+}
+
+fn render_ornament(state: &str, n: &str, delay: Option<&str>) -> Option<String> {
+    // id属性には属性用のエスケープ、hx-getのパスにはパーセントエンコードを使う
+    let mut path = utf8_percent_encode(n, PATH_SEGMENT).to_string();
+    // 次のスワップでも同じ間隔になるよう、指定があればdelayを引き継ぐ
+    if let Some(delay) = delay {
+        path.push_str("?delay=");
+        path.extend(utf8_percent_encode(delay, PATH_SEGMENT));
+    }
+    let path = html_escape::encode_double_quoted_attribute(&path);
+    let n = html_escape::encode_double_quoted_attribute(n);
+    let delay = html_escape::encode_double_quoted_attribute(delay.unwrap_or("2s"));
+    match state {
+        "on" => Some(format!(
+            "<div class=\"ornament on\" id=\"ornament{}\" hx-trigger=\"load delay:{} once\" hx-get=\"/23/ornament/off/{}\" hx-swap=\"outerHTML\"></div>",
+            n, delay, path
+        )),
+        "off" => Some(format!(
+            "<div class=\"ornament\" id=\"ornament{}\" hx-trigger=\"load delay:{} once\" hx-get=\"/23/ornament/on/{}\" hx-swap=\"outerHTML\"></div>",
+            n, delay, path
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OrnamentPattern {
+    #[default]
+    Uniform,
+    Alternate,
+}
+
+#[derive(Deserialize)]
+struct OrnamentsQuery {
+    count: Option<usize>,
+    start: Option<usize>,
+    state: Option<String>,
+    #[serde(default)]
+    pattern: OrnamentPattern,
+}
+
+#[utoipa::path(
+    get,
+    path = "/23/ornaments",
+    tag = "decorations",
+    params(
+        ("count" = Option<usize>, Query, description = "1 to 200"),
+        ("start" = Option<usize>, Query, description = "First ornament id"),
+        ("state" = Option<String>, Query, description = "on or off"),
+        ("pattern" = Option<String>, Query, description = "uniform (default) or alternate"),
+    ),
+    responses(
+        (status = 200, description = "The ornament fragments", body = String, content_type = "text/html"),
+        (status = 400, description = "Invalid query", body = String, content_type = "text/plain"),
+        (status = 418, description = "Invalid state"),
+    ),
+)]
+async fn get_ornaments(query: Result<Query<OrnamentsQuery>, QueryRejection>) -> Response {
+    let Ok(Query(query)) = query else {
+        return (StatusCode::BAD_REQUEST, "Invalid query").into_response();
+    };
+    let count = query.count.unwrap_or(DEFAULT_ORNAMENT_COUNT);
+    if !(1..=MAX_ORNAMENT_COUNT).contains(&count) {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("count must be between 1 and {}", MAX_ORNAMENT_COUNT),
+        )
+            .into_response();
+    }
+    // idはフロントエンドに合わせて1始まり
+    let start = query.start.unwrap_or(1);
+    if start.checked_add(count).is_none() {
+        return (StatusCode::BAD_REQUEST, "Invalid start").into_response();
+    }
+    let first_on = match query.state.as_deref() {
+        None | Some("off") => false,
+        Some("on") => true,
+        Some(_) => return StatusCode::IM_A_TEAPOT.into_response(),
+    };
+
+    // 1つずつのルートと同じrender_ornamentで描画する
+    let ornaments: String = (0..count)
+        .filter_map(|i| {
+            let on = match query.pattern {
+                OrnamentPattern::Uniform => first_on,
+                OrnamentPattern::Alternate => first_on ^ (i % 2 == 1),
+            };
+            let state = if on { "on" } else { "off" };
+            render_ornament(state, &(start + i).to_string(), None)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Html(ornaments).into_response()
+}
+
+#[derive(Clone, Copy)]
+pub struct LockfileLimits {
+    pub max_bytes: usize,
+    pub max_packages: usize,
+}
+
+fn lockfile_bad_request() -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, "Invalid lockfile".to_string())
+}
+
+#[derive(Serialize)]
+struct LockfileOrnament {
+    color: String,
+    top: u8,
+    left: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    package: Option<String>,
+    source: PackageSource,
+}
+
+// JSONではオーナメントと一緒に、種類ごとのパッケージ数を返す
+#[derive(Serialize)]
+struct LockfileOrnaments {
+    ornaments: Vec<LockfileOrnament>,
+    counts: SourceCounts,
+}
+
+#[derive(Serialize)]
+struct SourceCounts {
+    registry: usize,
+    git: usize,
+    path: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum PackageSource {
+    Registry,
+    Git,
+    Path,
+}
+
+impl PackageSource {
+    const ALL: [PackageSource; 3] = [
+        PackageSource::Registry,
+        PackageSource::Git,
+        PackageSource::Path,
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "registry" => Some(PackageSource::Registry),
+            "git" => Some(PackageSource::Git),
+            "path" => Some(PackageSource::Path),
+            _ => None,
+        }
+    }
+
+    fn header_name(self) -> &'static str {
+        match self {
+            PackageSource::Registry => "X-Lockfile-Registry",
+            PackageSource::Git => "X-Lockfile-Git",
+            PackageSource::Path => "X-Lockfile-Path",
+        }
+    }
+}
+
+// sourceがなければパス依存、git+で始まればgit依存、それ以外（sparse+や独自のレジストリも）はレジストリとみなす。
+// 文字列でないsourceは種類が分からないのでNone
+fn package_source(package: &toml::Value) -> Option<PackageSource> {
+    match package.get("source") {
+        None => Some(PackageSource::Path),
+        Some(toml::Value::String(source)) if source.starts_with("git+") => Some(PackageSource::Git),
+        Some(toml::Value::String(_)) => Some(PackageSource::Registry),
+        Some(_) => None,
+    }
+}
+
+fn parse_package_sources(sources: &str) -> Result<Vec<PackageSource>, (StatusCode, String)> {
+    sources
+        .split(',')
+        .map(|name| {
+            PackageSource::from_name(name.trim()).ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("Unknown package source {}", name.trim()),
+                )
+            })
+        })
+        .collect()
+}
+
+fn render_lockfile_ornaments(ornaments: &[LockfileOrnament]) -> String {
+    ornaments
+        .iter()
+        .map(|o| {
+            format!(
+                "<div style=\"background-color:{};top:{}px;left:{}px;\"></div>",
+                o.color, o.top, o.left
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// versionがなければ古い形式とみなして3として扱う
+fn lockfile_version(lockfile: &toml::Value) -> Result<i64, (StatusCode, String)> {
+    let version = match lockfile.get("version") {
+        Some(toml::Value::Integer(version)) => *version,
+        Some(_) => return Err(lockfile_bad_request()),
+        None => 3,
+    };
+    if !(3..=4).contains(&version) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("unsupported lockfile version {}", version),
+        ));
+    }
+    Ok(version)
+}
+
+// バージョンごとのチェックサムの置き場所の違いはここで吸収する
+fn package_checksum(
+    version: i64,
+    package: &toml::Value,
+) -> Result<Option<&str>, (StatusCode, String)> {
+    match version {
+        // v3とv4はどちらも各パッケージのchecksumキーに持つ
+        3 | 4 => match package.get("checksum") {
+            Some(toml::Value::String(checksum)) => Ok(Some(checksum)),
+            Some(_) => Err(lockfile_bad_request()),
+            None => Ok(None),
+        },
+        _ => Err(lockfile_bad_request()),
+    }
+}
+
+// "sha256:..."のようなアルゴリズム名の接頭辞があれば外してダイジェストだけを返す
+fn checksum_digest(checksum: &str) -> Result<&str, (StatusCode, String)> {
+    let Some((algorithm, digest)) = checksum.split_once(':') else {
+        return Ok(checksum);
+    };
+    match algorithm {
+        "sha256" | "sha512" | "blake3" => Ok(digest),
+        _ => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Unknown checksum algorithm {}", algorithm),
+        )),
+    }
+}
+
+fn invalid_checksum() -> (StatusCode, String) {
+    (
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "Invalid checksum".to_string(),
+    )
+}
+
+#[derive(Deserialize)]
+struct LockfileQuery {
+    #[serde(default)]
+    dedupe: bool,
+    #[serde(default)]
+    require_checksums: bool,
+    // 例: ?sources=registry,git（省略時はregistryのみ）
+    sources: Option<String>,
+}
+
+fn lockfile_too_large(limits: &LockfileLimits) -> (StatusCode, String) {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!("Lockfile exceeds {} bytes", limits.max_bytes),
+    )
+}
+
+// ボディ全体の上限を超えたときは、lockfileの上限を超えたときと同じ413にする
+fn multipart_error(e: MultipartError, limits: &LockfileLimits) -> (StatusCode, String) {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        lockfile_too_large(limits)
+    } else {
+        lockfile_bad_request()
+    }
+}
+
+// 全体を組み立てる前に、チャンクを受け取るたびにサイズを確認する
+fn push_lockfile_chunk(
+    buffer: &mut Vec<u8>,
+    chunk: &[u8],
+    limits: &LockfileLimits,
+) -> Result<(), (StatusCode, String)> {
+    if buffer.len() + chunk.len() > limits.max_bytes {
+        return Err(lockfile_too_large(limits));
+    }
+    buffer.extend_from_slice(chunk);
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/23/lockfile",
+    tag = "decorations",
+    params(
+        ("dedupe" = Option<bool>, Query, description = "Skip repeated checksums"),
+        ("require_checksums" = Option<bool>, Query, description = "Reject lockfiles without checksums"),
+        ("sources" = Option<String>, Query, description = "Comma-separated: registry, git, path (default: registry)"),
+    ),
+    request_body(
+        content((String = "multipart/form-data"), (String = "application/toml")),
+        description = "A Cargo.lock, as the lockfile form field or the raw body",
+    ),
+    responses(
+        (status = 200, description = "One ornament per package (JSON with Accept: application/json)", body = String, content_type = "text/html"),
+        (status = 400, description = "Invalid lockfile", body = String, content_type = "text/plain"),
+        (status = 413, description = "Lockfile too large", body = ApiErrorResponse),
+        (status = 422, description = "Invalid checksum, too many packages or unsupported version", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn process_lockfile(
+    State(state): State<AppState>,
+    query: Result<Query<LockfileQuery>, QueryRejection>,
+    request: Request,
+) -> Result<Response, (StatusCode, String)> {
+    let Ok(Query(query)) = query else {
+        return Err((StatusCode::BAD_REQUEST, "Invalid query".to_string()));
+    };
+    let limits = state.lockfile_limits;
+    let json = wants_json(request.headers());
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    // フィールドはボディより大きくならないので、上限内でContent-Length分だけ確保しておく
+    let capacity = content_length.unwrap_or(0).min(limits.max_bytes);
+    let content_type = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .map(|v| v.trim().to_ascii_lowercase());
+
+    // application/tomlかtext/plainならボディをそのままlockfileとして扱う
+    let lockfile_content = match content_type.as_deref() {
+        Some("application/toml") | Some("text/plain") => {
+            if content_length.is_some_and(|length| length > limits.max_bytes) {
+                return Err(lockfile_too_large(&limits));
+            }
+            let mut body = request.into_body().into_data_stream();
+            let mut buffer = Vec::with_capacity(capacity);
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk.map_err(|_| lockfile_bad_request())?;
+                push_lockfile_chunk(&mut buffer, &chunk, &limits)?;
+            }
+            Some(buffer)
+        }
+        _ => {
+            let mut multipart = Multipart::from_request(request, &())
+                .await
+                .map_err(|_| lockfile_bad_request())?;
+            // lockfileフィールドが複数あれば最後のものを使う
+            let mut lockfile_content = None;
+            while let Some(mut field) = multipart
+                .next_field()
+                .await
+                .map_err(|e| multipart_error(e, &limits))?
+            {
+                if field.name() == Some("lockfile") {
+                    let mut buffer = Vec::with_capacity(capacity);
+                    while let Some(chunk) = field
+                        .chunk()
+                        .await
+                        .map_err(|e| multipart_error(e, &limits))?
+                    {
+                        push_lockfile_chunk(&mut buffer, &chunk, &limits)?;
+                    }
+                    lockfile_content = Some(buffer);
+                }
+            }
+            lockfile_content
+        }
+    };
+
+    // フィールドがあっても中身が空なら400
+    let lockfile_content = lockfile_content
+        .and_then(|buffer| String::from_utf8(buffer).ok())
+        .filter(|content| !content.trim().is_empty())
+        .ok_or_else(lockfile_bad_request)?;
+
+    // TOMLとしてパース
+    let lockfile: toml::Value = match toml::from_str(&lockfile_content) {
+        Ok(l) => l,
+        _ => {
+            return Err(lockfile_bad_request());
+        }
+    };
+
+    let version = lockfile_version(&lockfile)?;
+
+    // packagesを取得
+    let packages = match lockfile.get("package") {
+        Some(toml::Value::Array(packages)) => packages,
+        _ => {
+            return Err(lockfile_bad_request());
+        }
+    };
+    if packages.len() > limits.max_packages {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!(
+                "Too many packages: at most {} are allowed",
+                limits.max_packages
+            ),
+        ));
+    }
+
+    let mut ornaments = Vec::new();
+    let mut rendered = HashSet::new();
+    let mut skipped = 0;
+    let mut without_checksum = 0;
+    let mut considered = 0;
+    let mut source_counts = [0usize; 3];
+    let sources = match &query.sources {
+        Some(sources) => parse_package_sources(sources)?,
+        None => vec![PackageSource::Registry],
+    };
+    for package in packages.iter() {
+        // 種類の分からないパッケージは飛ばす
+        let Some(source) = package_source(package) else {
+            continue;
+        };
+        source_counts[source as usize] += 1;
+        if !sources.contains(&source) {
+            continue;
+        }
+        considered += 1;
+        let Some(checksum) = package_checksum(version, package)? else {
+            without_checksum += 1;
+            continue;
+        };
+        let checksum = checksum_digest(checksum)?;
+        // チェックサムは少なくとも5バイト（10文字）必要で、16進数文字列である必要がある
+        if checksum.len() < 10 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid_checksum());
+        }
+
+        // 最初の6文字を色コードとして使用
+        let color = format!("#{}", &checksum[..6]);
+        // 次の2文字をtopとして使用
+        let top = u8::from_str_radix(&checksum[6..8], 16).map_err(|_| invalid_checksum())?;
+        // その次の2文字をleftとして使用
+        let left = u8::from_str_radix(&checksum[8..10], 16).map_err(|_| invalid_checksum())?;
+
+        // 同じチェックサムは同じ位置に重なるだけなので、指定があれば最初の1つだけ描画する
+        if query.dedupe && !rendered.insert(checksum) {
+            skipped += 1;
+            continue;
+        }
+        ornaments.push(LockfileOrnament {
+            color,
+            top,
+            left,
+            package: package
+                .get("name")
+                .and_then(|name| name.as_str())
+                .map(|name| name.to_string()),
+            source,
+        });
+    }
+
+    // require_checksumsの指定があれば、チェックサムが1つもないlockfileは失敗として扱う
+    if query.require_checksums && without_checksum == considered {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "no checksums found in lockfile".to_string(),
+        ));
+    }
+
+    let mut response = if json {
+        Json(LockfileOrnaments {
+            counts: SourceCounts {
+                registry: source_counts[PackageSource::Registry as usize],
+                git: source_counts[PackageSource::Git as usize],
+                path: source_counts[PackageSource::Path as usize],
+            },
+            ornaments,
+        })
+        .into_response()
+    } else {
+        Html(render_lockfile_ornaments(&ornaments)).into_response()
+    };
+    if query.dedupe {
+        response
+            .headers_mut()
+            .insert("X-Ornaments-Skipped", HeaderValue::from(skipped));
+    }
+    if query.require_checksums {
+        response.headers_mut().insert(
+            "X-Packages-Without-Checksum",
+            HeaderValue::from(without_checksum),
+        );
+    }
+    for source in PackageSource::ALL {
+        response.headers_mut().insert(
+            source.header_name(),
+            HeaderValue::from(source_counts[source as usize]),
+        );
+    }
+    Ok(response)
+}
+
+#[derive(OpenApi)]
+#[openapi(paths(
+    get_light_star,
+    set_star,
+    toggle_star,
+    list_present_colors,
+    get_present,
+    get_ornaments,
+    get_ornament,
+    process_lockfile,
+))]
+pub(crate) struct ApiDoc;
+
+pub(crate) fn routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/23/star", get(get_light_star).post(set_star))
+        .route("/23/star/toggle", post(toggle_star))
+        .route("/23/presents", get(list_present_colors))
+        .route("/23/present/:color", get(get_present))
+        .route("/23/ornaments", get(get_ornaments))
+        .route("/23/ornament/:state/:n", get(get_ornament))
+        // lockfile自体のサイズはprocess_lockfileの中で確認し、413はここで共通の形式に直す
+        .route(
+            "/23/lockfile",
+            post(process_lockfile)
+                .layer(DefaultBodyLimit::max(
+                    state.lockfile_limits.max_bytes + LOCKFILE_MULTIPART_OVERHEAD_BYTES,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    state.lockfile_limits.max_bytes,
+                    payload_too_large,
+                )),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_present_colors, LockfileLimits, DEFAULT_LOCKFILE_MAX_PACKAGES};
+    use crate::{testing::*, AppConfig, DEFAULT_CONTENT_SECURITY_POLICY};
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{HeaderValue, StatusCode},
+    };
+
+    fn lockfile_limit(max_bytes: usize) -> AppConfig {
+        AppConfig {
+            lockfile_limits: LockfileLimits {
+                max_bytes,
+                max_packages: DEFAULT_LOCKFILE_MAX_PACKAGES,
+            },
+            ..AppConfig::default()
+        }
+    }
+
+    fn get_json(uri: &str) -> Request {
+        let mut request = get(uri);
+        request
+            .headers_mut()
+            .insert("accept", "application/json".parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn present_is_html_by_default_and_json_on_request() {
+        let router = router(lazy_pool());
+
+        let response = send(&router, get("/23/present/red")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response.headers["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        assert!(response.text().contains("class=\"present red\""));
+        assert!(response.text().contains("hx-get=\"/23/present/blue\""));
+
+        let response = send(&router, get_json("/23/present/purple")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json(),
+            serde_json::json!({ "color": "purple", "next": "red" })
+        );
+
+        for request in [get("/23/present/green"), get_json("/23/present/green")] {
+            assert_eq!(send(&router, request).await.status, StatusCode::IM_A_TEAPOT);
+        }
+    }
+
+    #[tokio::test]
+    async fn ornament_is_html_by_default_and_json_on_request() {
+        let router = router(lazy_pool());
+
+        let response = send(&router, get("/23/ornament/on/3")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response.headers["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+        assert!(response.text().contains("class=\"ornament on\""));
+        assert!(response.text().contains("hx-get=\"/23/ornament/off/3\""));
+
+        let response = send(&router, get_json("/23/ornament/on/3")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json(),
+            serde_json::json!({ "id": "3", "state": "on", "next_state": "off" })
+        );
+
+        for request in [get("/23/ornament/dim/3"), get_json("/23/ornament/dim/3")] {
+            assert_eq!(send(&router, request).await.status, StatusCode::IM_A_TEAPOT);
+        }
+    }
+
+    const LOCKFILE: &str = r#"
+[[package]]
+name = "a"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "337789faa0372648a8ac286b2f92a53121fe118f12e29009ac504872a5413cc6"
+
+[[package]]
+name = "d"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "c22b6ff4cc7ea17e4ee3f4cd5e5df2c1a4d0d0a0e9b7c4f0f2d5d8c3a1b0e9f8"
+"#;
+
+    fn multipart(fields: &[(&str, &str)]) -> String {
+        let mut body = String::new();
+        for (name, value) in fields {
+            body.push_str(&format!(
+                "--boundary\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                name, value
+            ));
+        }
+        body.push_str("--boundary--\r\n");
+        body
+    }
+
+    #[tokio::test]
+    async fn raw_and_multipart_uploads_render_the_same() {
+        let router = router(lazy_pool());
+        let raw = send(&router, post("/23/lockfile", "application/toml", LOCKFILE)).await;
+        let plain = send(&router, post("/23/lockfile", "text/plain", LOCKFILE)).await;
+        let form = send(
+            &router,
+            post(
+                "/23/lockfile",
+                "multipart/form-data; boundary=boundary",
+                multipart(&[("lockfile", LOCKFILE)]),
+            ),
+        )
+        .await;
+        assert_eq!(raw.status, StatusCode::OK);
+        assert_eq!(form.status, StatusCode::OK);
+        assert_eq!(raw.text(), form.text());
+        assert_eq!(plain.text(), form.text());
+        assert_eq!(raw.text().matches("<div").count(), 2);
+
+        let empty = send(&router, post("/23/lockfile", "application/toml", " \n")).await;
+        assert_eq!(empty.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn ornament_ids_are_escaped_in_every_attribute() {
+        let router = router(lazy_pool());
+        for (uri, expected) in [
+            (
+                "/23/ornament/on/1%2F..%2Fstar",
+                "<div class=\"ornament on\" id=\"ornament1/../star\" hx-trigger=\"load delay:2s once\" hx-get=\"/23/ornament/off/1%2F..%2Fstar\" hx-swap=\"outerHTML\"></div>",
+            ),
+            (
+                "/23/ornament/off/a%3Fb%3Dc",
+                "<div class=\"ornament\" id=\"ornamenta?b=c\" hx-trigger=\"load delay:2s once\" hx-get=\"/23/ornament/on/a%3Fb%3Dc\" hx-swap=\"outerHTML\"></div>",
+            ),
+            (
+                "/23/ornament/on/x%22y",
+                "<div class=\"ornament on\" id=\"ornamentx&quot;y\" hx-trigger=\"load delay:2s once\" hx-get=\"/23/ornament/off/x%22y\" hx-swap=\"outerHTML\"></div>",
+            ),
+        ] {
+            let response = send(&router, get(uri)).await;
+            assert_eq!(response.status, StatusCode::OK, "{}", uri);
+            assert_eq!(response.text(), expected, "{}", uri);
+        }
+
+        let response = send(&router, get("/23/ornament/on/a%0Ab")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn present_colors_cycle_from_the_configured_list() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/23/presents")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json(),
+            serde_json::json!({ "colors": ["red", "blue", "purple"] })
+        );
+
+        let config = AppConfig {
+            present_colors: parse_present_colors(" green, gold,green ,,"),
+            ..AppConfig::default()
+        };
+        let router = router_with(lazy_pool(), config);
+        let response = send(&router, get("/23/presents")).await;
+        assert_eq!(
+            response.json(),
+            serde_json::json!({ "colors": ["green", "gold"] })
+        );
+
+        let response = send(&router, get("/23/present/gold")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response
+            .text()
+            .contains("class=\"present gold\" hx-get=\"/23/present/green\""));
+
+        let mut request = get("/23/present/green");
+        request
+            .headers_mut()
+            .insert("accept", "application/json".parse().unwrap());
+        let response = send(&router, request).await;
+        assert_eq!(
+            response.json(),
+            serde_json::json!({ "color": "green", "next": "gold" })
+        );
+
+        let response = send(&router, get("/23/present/red")).await;
+        assert_eq!(response.status, StatusCode::IM_A_TEAPOT);
+    }
+
+    #[tokio::test]
+    async fn html_responses_carry_security_headers() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/23/star")).await;
+        assert_eq!(response.headers["x-content-type-options"], "nosniff");
+        assert_eq!(
+            response.headers["content-security-policy"],
+            DEFAULT_CONTENT_SECURITY_POLICY
+        );
+        assert_eq!(
+            response.headers["referrer-policy"],
+            "strict-origin-when-cross-origin"
+        );
+
+        let response = send(&router, get("/23/presents")).await;
+        assert!(!response.headers.contains_key("content-security-policy"));
+
+        let config = AppConfig {
+            content_security_policy: HeaderValue::from_static("default-src 'none'"),
+            ..AppConfig::default()
+        };
+        let router = router_with(lazy_pool(), config);
+        let response = send(&router, get("/23/star")).await;
+        assert_eq!(
+            response.headers["content-security-policy"],
+            "default-src 'none'"
+        );
+    }
+
+    #[tokio::test]
+    async fn ornaments_renders_a_row_of_ornaments() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/23/ornaments")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let text = response.text();
+        assert_eq!(text.lines().count(), 7);
+        assert!(text.contains("id=\"ornament1\""));
+        assert!(text.contains("id=\"ornament7\""));
+        assert!(!text.contains("ornament on"));
+
+        let response = send(&router, get("/23/ornaments?count=3&pattern=alternate")).await;
+        let lines: Vec<String> = response.text().lines().map(str::to_string).collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("<div class=\"ornament\" id=\"ornament1\""));
+        assert!(lines[1].starts_with("<div class=\"ornament on\" id=\"ornament2\""));
+        assert!(lines[2].starts_with("<div class=\"ornament\" id=\"ornament3\""));
+
+        let response = send(&router, get("/23/ornaments?count=1000")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.text(), "count must be between 1 and 200");
+
+        let response = send(&router, get("/23/ornaments?count=many")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn lockfile_limits_reject_large_uploads() {
+        let config = AppConfig {
+            lockfile_limits: LockfileLimits {
+                max_bytes: 1024,
+                max_packages: 1,
+            },
+            ..AppConfig::default()
+        };
+        let router = router_with(lazy_pool(), config);
+        let response = send(&router, post("/23/lockfile", "application/toml", LOCKFILE)).await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.text(), "Too many packages: at most 1 are allowed");
+
+        let router = router_with(lazy_pool(), lockfile_limit(64));
+        let response = send(&router, post("/23/lockfile", "application/toml", LOCKFILE)).await;
+        assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.json()["error"]["details"]["limit_bytes"], 64);
+
+        let response = send(
+            &router,
+            request(
+                "POST",
+                "/23/lockfile",
+                Some("multipart/form-data; boundary=boundary"),
+                Body::from(multipart(&[("lockfile", LOCKFILE)])),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn dedupe_draws_each_checksum_once() {
+        let router = router(lazy_pool());
+        let package = LOCKFILE.split("\n\n").next().unwrap();
+        let lockfile = ["a", "b", "c"]
+            .iter()
+            .map(|name| package.replace("name = \"a\"", &format!("name = \"{}\"", name)) + "\n\n")
+            .collect::<String>();
+        let response = send(
+            &router,
+            post(
+                "/23/lockfile?dedupe=true",
+                "application/toml",
+                lockfile.clone(),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.text(),
+            "<div style=\"background-color:#337789;top:250px;left:160px;\"></div>"
+        );
+        assert_eq!(response.headers["x-ornaments-skipped"], "2");
+
+        let response = send(&router, post("/23/lockfile", "application/toml", lockfile)).await;
+        assert_eq!(response.text().matches("<div").count(), 3);
+        assert!(!response.headers.contains_key("x-ornaments-skipped"));
+    }
+
+    #[tokio::test]
+    async fn lockfile_ornaments_as_json() {
+        let router = router(lazy_pool());
+        let mut request = post("/23/lockfile", "application/toml", LOCKFILE);
+        request
+            .headers_mut()
+            .insert("accept", "application/json".parse().unwrap());
+        let response = send(&router, request).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers["content-type"], "application/json");
+        assert_eq!(
+            response.json(),
+            serde_json::json!({
+                "ornaments": [
+                    { "color": "#337789", "top": 250, "left": 160, "package": "a", "source": "registry" },
+                    { "color": "#c22b6f", "top": 244, "left": 204, "package": "d", "source": "registry" },
+                ],
+                "counts": { "registry": 2, "git": 0, "path": 0 },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn last_lockfile_field_wins() {
+        let router = router(lazy_pool());
+        let package = LOCKFILE.split("\n\n").next().unwrap();
+        let response = send(
+            &router,
+            post(
+                "/23/lockfile",
+                "multipart/form-data; boundary=boundary",
+                multipart(&[("lockfile", "not toml ["), ("lockfile", package)]),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.text(),
+            "<div style=\"background-color:#337789;top:250px;left:160px;\"></div>"
+        );
+    }
+
+    #[tokio::test]
+    async fn declared_oversized_lockfile_is_rejected_up_front() {
+        let router = router_with(lazy_pool(), lockfile_limit(64));
+        let mut request = post("/23/lockfile", "application/toml", "");
+        request
+            .headers_mut()
+            .insert("content-length", "65".parse().unwrap());
+        let response = send(&router, request).await;
+        assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.json()["error"]["details"]["limit_bytes"], 64);
+    }
+
+    #[tokio::test]
+    async fn lockfile_versions_are_checked() {
+        let router = router(lazy_pool());
+        let package = LOCKFILE.split("\n\n").next().unwrap();
+        for version in ["", "version = 3\n", "version = 4\n"] {
+            let lockfile = format!("{}{}", version, package);
+            let response = send(&router, post("/23/lockfile", "application/toml", lockfile)).await;
+            assert_eq!(response.status, StatusCode::OK, "{:?}", version);
+            assert_eq!(
+                response.text(),
+                "<div style=\"background-color:#337789;top:250px;left:160px;\"></div>"
+            );
+        }
+
+        let lockfile = format!("version = 5\n{}", package);
+        let response = send(&router, post("/23/lockfile", "application/toml", lockfile)).await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.text(), "unsupported lockfile version 5");
+
+        let lockfile = format!("version = \"4\"\n{}", package);
+        let response = send(&router, post("/23/lockfile", "application/toml", lockfile)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn star_state_can_be_set_and_toggled() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/23/star")).await;
+        assert_eq!(response.text(), "<div id=\"star\" class=\"lit\"></div>");
+
+        let response = send(&router, post("/23/star?lit=false", "text/plain", "")).await;
+        assert_eq!(response.text(), "<div id=\"star\"></div>");
+        let response = send(&router, get("/23/star?poll=true")).await;
+        assert_eq!(
+            response.text(),
+            "<div id=\"star\" hx-get=\"/23/star\" hx-trigger=\"every 5s\" hx-swap=\"outerHTML\"></div>"
+        );
+
+        let response = send(&router, post("/23/star/toggle", "text/plain", "")).await;
+        assert_eq!(response.text(), "<div id=\"star\" class=\"lit\"></div>");
+        let response = send(&router, get("/23/star")).await;
+        assert_eq!(response.text(), "<div id=\"star\" class=\"lit\"></div>");
+
+        let response = send(&router, post("/23/star?lit=maybe", "text/plain", "")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn ornament_delay_carries_into_the_next_swap() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/23/ornament/on/1?delay=500ms")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.text(),
+            "<div class=\"ornament on\" id=\"ornament1\" hx-trigger=\"load delay:500ms once\" hx-get=\"/23/ornament/off/1?delay=500ms\" hx-swap=\"outerHTML\"></div>"
+        );
+
+        let response = send(&router, get("/23/ornament/off/1")).await;
+        assert!(response
+            .text()
+            .contains("hx-trigger=\"load delay:2s once\""));
+
+        for delay in ["10ms", "61s", "2m", "s", "1.5s"] {
+            let response = send(&router, get(&format!("/23/ornament/on/1?delay={}", delay))).await;
+            assert_eq!(response.status, StatusCode::BAD_REQUEST, "{}", delay);
+            assert_eq!(response.text(), "Invalid delay");
+        }
+    }
+
+    #[test]
+    fn rotate_hue_moves_red_to_green() {
+        assert_eq!(super::rotate_hue([255, 0, 0], 120.0), [0, 255, 0]);
+        assert_eq!(super::rotate_hue([0, 255, 0], 120.0), [0, 0, 255]);
+    }
+
+    #[test]
+    fn rotate_hue_keeps_grays() {
+        for gray in [[0, 0, 0], [128, 128, 128], [255, 255, 255]] {
+            assert_eq!(super::rotate_hue(gray, 120.0), gray);
+        }
+    }
+
+    #[tokio::test]
+    async fn hex_presents_cycle_by_hue() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/23/present/hex-ff0000")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response.text().starts_with(
+            "<div class=\"present\" style=\"background-color:#ff0000\" hx-get=\"/23/present/hex-00ff00\""
+        ));
+
+        let mut request = get("/23/present/%23ff0000");
+        request
+            .headers_mut()
+            .insert("accept", "application/json".parse().unwrap());
+        assert_eq!(
+            send(&router, request).await.json(),
+            serde_json::json!({ "color": "#ff0000", "next": "hex-00ff00" })
+        );
+
+        for uri in ["/23/present/hex-ff880", "/23/present/%23ff880"] {
+            let response = send(&router, get(uri)).await;
+            assert_eq!(response.status, StatusCode::IM_A_TEAPOT, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn require_checksums_counts_packages_without_one() {
+        let router = router(lazy_pool());
+        let with_checksum = LOCKFILE.split("\n\n").next().unwrap();
+        let without_checksum = "\n[[package]]\nname = \"b\"\nsource = \"registry+https://github.com/rust-lang/crates.io-index\"\n";
+
+        let response = send(
+            &router,
+            post(
+                "/23/lockfile?require_checksums=true",
+                "application/toml",
+                without_checksum,
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.text(), "no checksums found in lockfile");
+
+        let lockfile = format!("{}\n{}", with_checksum, without_checksum);
+        let response = send(
+            &router,
+            post(
+                "/23/lockfile?require_checksums=true",
+                "application/toml",
+                lockfile,
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers["x-packages-without-checksum"], "1");
+        assert_eq!(
+            response.text(),
+            "<div style=\"background-color:#337789;top:250px;left:160px;\"></div>"
+        );
+
+        let response = send(
+            &router,
+            post(
+                "/23/lockfile?require_checksums=true",
+                "application/toml",
+                with_checksum,
+            ),
+        )
+        .await;
+        assert_eq!(response.headers["x-packages-without-checksum"], "0");
+
+        // 指定がなければチェックサムのないlockfileも受け付ける
+        let response = send(
+            &router,
+            post("/23/lockfile", "application/toml", without_checksum),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(!response.headers.contains_key("x-packages-without-checksum"));
+    }
+
+    #[test]
+    fn checksum_digest_strips_known_algorithms() {
+        for algorithm in ["sha256", "sha512", "blake3"] {
+            let checksum = format!("{}:337789faa0", algorithm);
+            assert_eq!(super::checksum_digest(&checksum), Ok("337789faa0"));
+        }
+        assert_eq!(super::checksum_digest("337789faa0"), Ok("337789faa0"));
+    }
+
+    #[tokio::test]
+    async fn prefixed_checksums_are_drawn_from_the_digest() {
+        let router = router(lazy_pool());
+        let package = LOCKFILE.split("\n\n").next().unwrap();
+        let lockfile = package.replace("checksum = \"", "checksum = \"sha256:");
+        let response = send(&router, post("/23/lockfile", "application/toml", lockfile)).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.text(),
+            "<div style=\"background-color:#337789;top:250px;left:160px;\"></div>"
+        );
+
+        let lockfile = package.replace("checksum = \"", "checksum = \"md5:");
+        let response = send(&router, post("/23/lockfile", "application/toml", lockfile)).await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.text(), "Unknown checksum algorithm md5");
+    }
+
+    #[tokio::test]
+    async fn ornaments_start_state_and_pattern() {
+        let router = router(lazy_pool());
+        let response = send(
+            &router,
+            get("/23/ornaments?count=3&start=10&state=on&pattern=alternate"),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        let lines: Vec<String> = response.text().lines().map(str::to_string).collect();
+        assert!(lines[0].starts_with("<div class=\"ornament on\" id=\"ornament10\""));
+        assert!(lines[1].starts_with("<div class=\"ornament\" id=\"ornament11\""));
+        assert!(lines[2].starts_with("<div class=\"ornament on\" id=\"ornament12\""));
+
+        let response = send(&router, get("/23/ornaments?count=2&state=on")).await;
+        assert_eq!(response.text().matches("ornament on").count(), 2);
+
+        let response = send(&router, get("/23/ornaments?state=dim")).await;
+        assert_eq!(response.status, StatusCode::IM_A_TEAPOT);
+        let response = send(&router, get("/23/ornaments?count=0")).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        let response = send(&router, get(&format!("/23/ornaments?start={}", usize::MAX))).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.text(), "Invalid start");
+    }
+
+    const MIXED_SOURCES: &str = r#"
+[[package]]
+name = "a"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "337789faa0372648a8ac286b2f92a53121fe118f12e29009ac504872a5413cc6"
+
+[[package]]
+name = "g"
+source = "git+https://github.com/7crabs/g#0123456"
+checksum = "ffffffffffffffff"
+
+[[package]]
+name = "p"
+checksum = "0000000000000000"
+"#;
+
+    #[tokio::test]
+    async fn only_registry_packages_are_drawn_by_default() {
+        let router = router(lazy_pool());
+        let response = send(
+            &router,
+            post("/23/lockfile", "application/toml", MIXED_SOURCES),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.text(),
+            "<div style=\"background-color:#337789;top:250px;left:160px;\"></div>"
+        );
+        assert_eq!(response.headers["x-lockfile-registry"], "1");
+        assert_eq!(response.headers["x-lockfile-git"], "1");
+        assert_eq!(response.headers["x-lockfile-path"], "1");
+
+        let response = send(
+            &router,
+            post(
+                "/23/lockfile?sources=registry,git,path",
+                "application/toml",
+                MIXED_SOURCES,
+            ),
+        )
+        .await;
+        assert_eq!(response.text().matches("<div").count(), 3);
+
+        let response = send(
+            &router,
+            post(
+                "/23/lockfile?sources=svn",
+                "application/toml",
+                MIXED_SOURCES,
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn json_lockfile_response_counts_each_source() {
+        let mut request = post(
+            "/23/lockfile?sources=git",
+            "application/toml",
+            MIXED_SOURCES,
+        );
+        request
+            .headers_mut()
+            .insert("accept", "application/json".parse().unwrap());
+        let response = send(&router(lazy_pool()), request).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.json(),
+            serde_json::json!({
+                "ornaments": [
+                    {"color": "#ffffff", "top": 255, "left": 255, "package": "g", "source": "git"},
+                ],
+                "counts": {"registry": 1, "git": 1, "path": 1},
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_source_kind_skips_the_package() {
+        let package = LOCKFILE.split("\n\n").next().unwrap();
+        let lockfile = format!(
+            "{}\n{}",
+            package,
+            r#"
+[[package]]
+name = "c"
+source = 42
+checksum = "ffffffffff"
+"#
+        );
+        let response = send(
+            &router(lazy_pool()),
+            post("/23/lockfile", "application/toml", lockfile),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.text(),
+            "<div style=\"background-color:#337789;top:250px;left:160px;\"></div>"
+        );
+        assert_eq!(response.headers["x-lockfile-registry"], "1");
+        assert_eq!(response.headers["x-lockfile-path"], "0");
+    }
+
+    #[tokio::test]
+    async fn oversized_lockfiles_are_rejected() {
+        let router = router_with(lazy_pool(), lockfile_limit(64));
+        let content_type = "multipart/form-data; boundary=boundary";
+        let oversized = "#".repeat(100);
+        let requests = [
+            post("/23/lockfile", "application/toml", oversized.clone()),
+            post(
+                "/23/lockfile",
+                content_type,
+                multipart(&[("lockfile", &oversized)]),
+            ),
+            // lockfileは小さくても、ボディ全体が上限を超えれば読み切る前に断る
+            post(
+                "/23/lockfile",
+                content_type,
+                multipart(&[
+                    (
+                        "padding",
+                        &"#".repeat(super::LOCKFILE_MULTIPART_OVERHEAD_BYTES),
+                    ),
+                    ("lockfile", LOCKFILE),
+                ]),
+            ),
+        ];
+        for request in requests {
+            let response = send(&router, request).await;
+            assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+            let error = response.json();
+            assert_eq!(error["error"]["code"], "payload_too_large");
+            assert_eq!(error["error"]["details"]["limit_bytes"], 64);
+        }
+    }
+}
@@ -0,0 +1,1367 @@
+use axum::{
+    extract::{DefaultBodyLimit, Json, MatchedPath, Request, State},
+    handler::HandlerWithoutStateExt,
+    http::{
+        header::{self, HeaderMap, CONTENT_TYPE},
+        HeaderValue, StatusCode, Uri,
+    },
+    middleware,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::DateTime;
+use jsonwebtoken::Header;
+use leaky_bucket::RateLimiter;
+use rand::SeedableRng;
+use serde::Serialize;
+use shuttle_runtime::SecretStore;
+use std::{
+    collections::HashSet,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::Duration,
+};
+use tower_http::{services::ServeDir, timeout::TimeoutLayer};
+use tracing::Instrument;
+use utoipa::{OpenApi, ToSchema};
+use utoipa_swagger_ui::{self as swagger_ui, SwaggerUi};
+use uuid::Uuid;
+
+mod day02;
+mod day05;
+mod day09;
+mod day12;
+mod day16;
+mod day19;
+mod day23;
+mod error;
+mod repository;
+
+use error::{AppError, DatabaseErrorLog};
+
+use day09::{BUCKET_SIZE, REFILL_INTERVAL};
+use day12::Board;
+use day16::{ALGORITHM, HEADER};
+use day19::{PaginationTokens, WriteLimiter};
+
+pub use day12::WinMessages;
+pub use day16::{load_gift_schema, GiftKeys};
+pub use day19::{parse_trusted_proxies, spawn_sweep_tasks, DraftLimits};
+pub use day23::{parse_present_colors, LockfileLimits};
+
+const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+
+const DEFAULT_BODY_LIMIT_BYTES: usize = 1024 * 1024;
+
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+// HTMXはunpkgから読み込み、インジケーター用のスタイルをインラインで挿入する
+const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'self' https://unpkg.com; style-src 'self' 'unsafe-inline'; img-src 'self' data:; frame-ancestors 'none'";
+
+#[derive(Clone)]
+pub struct AppState {
+    limiter: Arc<Mutex<RateLimiter>>,
+    board: Arc<Mutex<Board>>,
+    rng: Arc<Mutex<rand::rngs::StdRng>>,
+    pool: sqlx::PgPool,
+    pagination_tokens: PaginationTokens,
+    gift_keys: Arc<GiftKeys>,
+    gift_schema: Option<Arc<jsonschema::Validator>>,
+    draft_limits: DraftLimits,
+    lockfile_limits: LockfileLimits,
+    body_limits: BodyLimits,
+    win_messages: Arc<WinMessages>,
+    write_limiter: WriteLimiter,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+    default_per_page: i64,
+    present_colors: Arc<Vec<String>>,
+    star_lit: Arc<AtomicBool>,
+    assets_dir: Arc<PathBuf>,
+    content_security_policy: HeaderValue,
+    request_timeout: Duration,
+    migrations: Migrations,
+}
+
+// シークレットから読む設定値。テストではDefaultから必要な分だけ上書きして使う
+pub struct AppConfig {
+    pub gift_keys: GiftKeys,
+    pub gift_schema: Option<jsonschema::Validator>,
+    pub win_messages: WinMessages,
+    pub draft_limits: DraftLimits,
+    pub lockfile_limits: LockfileLimits,
+    pub body_limits: BodyLimits,
+    pub pagination_token_ttl: Duration,
+    pub pagination_token_retention: Duration,
+    pub max_pagination_tokens: i64,
+    pub deterministic_tokens: bool,
+    pub write_bucket_size: usize,
+    pub write_refill_interval: Duration,
+    pub default_per_page: i64,
+    pub present_colors: Vec<String>,
+    pub assets_dir: PathBuf,
+    pub content_security_policy: HeaderValue,
+    pub request_timeout: Duration,
+    pub trusted_proxies: Vec<IpAddr>,
+    pub migrations: Migrations,
+}
+
+// リクエストボディの上限。/23/lockfileはLockfileLimitsで別に管理する
+#[derive(Clone, Copy)]
+pub struct BodyLimits {
+    pub default_bytes: usize,
+    pub gift_bytes: usize,
+}
+
+impl Default for BodyLimits {
+    fn default() -> Self {
+        Self {
+            default_bytes: DEFAULT_BODY_LIMIT_BYTES,
+            gift_bytes: day16::DEFAULT_GIFT_BODY_LIMIT_BYTES,
+        }
+    }
+}
+
+impl BodyLimits {
+    pub fn from_secrets(secrets: &SecretStore) -> Result<Self, String> {
+        let defaults = BodyLimits::default();
+        let read = |name: &str, default: usize| match secrets.get(name) {
+            Some(value) => match value.parse::<usize>() {
+                Ok(0) | Err(_) => Err(format!("{} must be a positive number of bytes", name)),
+                Ok(bytes) => Ok(bytes),
+            },
+            None => Ok(default),
+        };
+        let limits = BodyLimits {
+            default_bytes: read("BODY_LIMIT_BYTES", defaults.default_bytes)?,
+            gift_bytes: read("GIFT_BODY_LIMIT_BYTES", defaults.gift_bytes)?,
+        };
+        if limits.gift_bytes > limits.default_bytes {
+            return Err("GIFT_BODY_LIMIT_BYTES must not exceed BODY_LIMIT_BYTES".to_string());
+        }
+        Ok(limits)
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            gift_keys: GiftKeys::default(),
+            gift_schema: None,
+            win_messages: WinMessages::default(),
+            draft_limits: DraftLimits {
+                max_author_chars: day19::DEFAULT_MAX_AUTHOR_CHARS,
+                max_quote_chars: day19::DEFAULT_MAX_QUOTE_CHARS,
+            },
+            lockfile_limits: LockfileLimits {
+                max_bytes: day23::DEFAULT_LOCKFILE_MAX_BYTES,
+                max_packages: day23::DEFAULT_LOCKFILE_MAX_PACKAGES,
+            },
+            body_limits: BodyLimits::default(),
+            pagination_token_ttl: Duration::from_secs(day19::DEFAULT_TOKEN_TTL_SECS),
+            pagination_token_retention: Duration::from_secs(day19::DEFAULT_TOKEN_TTL_SECS),
+            max_pagination_tokens: day19::DEFAULT_MAX_TOKENS,
+            deterministic_tokens: false,
+            write_bucket_size: day19::DEFAULT_WRITE_BUCKET_SIZE,
+            write_refill_interval: Duration::from_secs(day19::DEFAULT_WRITE_REFILL_INTERVAL),
+            default_per_page: day19::DEFAULT_QUOTES_PER_PAGE,
+            present_colors: day23::DEFAULT_PRESENT_COLORS
+                .iter()
+                .map(|c| c.to_string())
+                .collect(),
+            assets_dir: PathBuf::from("assets"),
+            content_security_policy: HeaderValue::from_static(DEFAULT_CONTENT_SECURITY_POLICY),
+            request_timeout: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            trusted_proxies: Vec::new(),
+            migrations: Migrations::default(),
+        }
+    }
+}
+
+impl AppState {
+    pub fn new(pool: sqlx::PgPool, config: AppConfig) -> Self {
+        HEADER.get_or_init(|| Header::new(ALGORITHM));
+        Self {
+            limiter: Arc::new(Mutex::new(
+                RateLimiter::builder()
+                    .initial(BUCKET_SIZE)
+                    .max(BUCKET_SIZE)
+                    .interval(Duration::from_secs(REFILL_INTERVAL))
+                    .build(),
+            )),
+            board: Arc::new(Mutex::new(Board::default())),
+            rng: Arc::new(Mutex::new(rand::rngs::StdRng::seed_from_u64(2024))),
+            pool,
+            pagination_tokens: PaginationTokens::new(
+                config.pagination_token_ttl,
+                config.pagination_token_retention,
+                config.max_pagination_tokens,
+                config.deterministic_tokens,
+            ),
+            gift_keys: Arc::new(config.gift_keys),
+            gift_schema: config.gift_schema.map(Arc::new),
+            draft_limits: config.draft_limits,
+            lockfile_limits: config.lockfile_limits,
+            body_limits: config.body_limits,
+            win_messages: Arc::new(config.win_messages),
+            write_limiter: WriteLimiter::new(
+                config.write_bucket_size,
+                config.write_refill_interval,
+            ),
+            default_per_page: config.default_per_page.clamp(1, day19::MAX_QUOTES_PER_PAGE),
+            present_colors: Arc::new(config.present_colors),
+            star_lit: Arc::new(AtomicBool::new(true)),
+            assets_dir: Arc::new(config.assets_dir),
+            content_security_policy: config.content_security_policy,
+            request_timeout: config.request_timeout,
+            trusted_proxies: Arc::new(config.trusted_proxies),
+            migrations: config.migrations,
+        }
+    }
+}
+
+pub fn build_router(state: AppState) -> Router {
+    let assets_dir = state.assets_dir.to_path_buf();
+    let assets = Router::new()
+        .nest_service(
+            "/assets",
+            ServeDir::new(&assets_dir)
+                .fallback((move |uri: Uri| spa_fallback(assets_dir.clone(), uri)).into_service()),
+        )
+        .layer(middleware::from_fn(asset_cache_headers));
+
+    let content_security_policy = state.content_security_policy.clone();
+    let request_timeout = state.request_timeout;
+    let body_limit = state.body_limits.default_bytes;
+    Router::new()
+        .route("/", get(hello_world))
+        .route("/live", get(live))
+        .route("/ready", get(ready))
+        .route("/health", get(health))
+        .route("/metrics", get(metrics))
+        .route("/-1/seek", get(seek))
+        .route("/openapi.json", get(openapi_json))
+        // Swagger UIのファイルはクレートに同梱されたものを配る
+        .merge(SwaggerUi::new("/docs").config(swagger_ui::Config::from("/openapi.json")))
+        .merge(day02::routes())
+        .merge(day05::routes())
+        .merge(day09::routes())
+        .merge(day12::routes())
+        .merge(day16::routes(&state))
+        .merge(day19::routes(&state))
+        .merge(day23::routes(&state))
+        .merge(assets)
+        // ルートごとの上限はこれより内側で上書きされる
+        .layer(DefaultBodyLimit::max(body_limit))
+        .layer(middleware::from_fn_with_state(
+            body_limit,
+            payload_too_large,
+        ))
+        .with_state(state)
+        .layer(middleware::from_fn_with_state(
+            content_security_policy,
+            security_headers,
+        ))
+        // TimeoutLayerは408を返すので、外側で504に置き換える
+        .layer(TimeoutLayer::new(request_timeout))
+        .layer(middleware::map_response(gateway_timeout))
+        .layer(middleware::from_fn(log_requests))
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "probes",
+    responses((status = 200, description = "Hello, bird!", body = String, content_type = "text/plain")),
+)]
+async fn hello_world() -> &'static str {
+    "Hello, bird!"
+}
+
+// プロセスが動いていれば常に200（DBには触らない）
+#[utoipa::path(
+    get,
+    path = "/live",
+    tag = "probes",
+    responses((status = 200, description = "The process is running", body = String, content_type = "text/plain")),
+)]
+async fn live() -> (StatusCode, String) {
+    (StatusCode::OK, "OK".to_string())
+}
+
+// DBに接続でき、読み込んだマイグレーションがすべて適用済みならトラフィックを受けられる
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "probes",
+    responses(
+        (status = 200, description = "Ready to receive traffic", body = String, content_type = "text/plain"),
+        (status = 503, description = "Database unavailable or migrations pending", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn ready(State(state): State<AppState>) -> (StatusCode, String) {
+    let applied =
+        match sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(&state.pool)
+            .await
+        {
+            Ok(applied) => applied,
+            Err(e) => {
+                tracing::warn!(error = ?e, "readiness check failed");
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Not ready: database unavailable".to_string(),
+                );
+            }
+        };
+    if !state.migrations.versions().all(|v| applied.contains(&v)) {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Not ready: migrations pending".to_string(),
+        );
+    }
+    (StatusCode::OK, "OK".to_string())
+}
+
+#[derive(Serialize, ToSchema)]
+struct DatabaseHealth {
+    status: &'static str,
+    latency_ms: Option<f64>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct MigrationHealth {
+    status: &'static str,
+    applied: Option<usize>,
+    expected: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+struct HealthReport {
+    status: &'static str,
+    database: DatabaseHealth,
+    migrations: MigrationHealth,
+    milk_tokens_available: Option<usize>,
+    pagination_tokens: Option<i64>,
+}
+
+// 監視用の詳細な状態。DBに接続できないときだけdownとして503を返し、
+// 応答が遅い（タイムアウト）・未適用のマイグレーションがあるなどはdegradedとして200で返す
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "probes",
+    responses(
+        (status = 200, description = "ok or degraded", body = HealthReport),
+        (status = 503, description = "Database down", body = HealthReport),
+    ),
+)]
+async fn health(State(state): State<AppState>) -> (StatusCode, Json<HealthReport>) {
+    let started = std::time::Instant::now();
+    let database = match tokio::time::timeout(
+        HEALTH_CHECK_TIMEOUT,
+        sqlx::query("SELECT 1").execute(&state.pool),
+    )
+    .await
+    {
+        Ok(Ok(_)) => DatabaseHealth {
+            status: "ok",
+            latency_ms: Some(started.elapsed().as_secs_f64() * 1000.0),
+        },
+        Ok(Err(e)) => {
+            tracing::warn!(error = ?e, "health check: database unavailable");
+            DatabaseHealth {
+                status: "down",
+                latency_ms: None,
+            }
+        }
+        Err(_) => DatabaseHealth {
+            status: "degraded",
+            latency_ms: None,
+        },
+    };
+    let database_ok = database.status == "ok";
+
+    let expected: HashSet<i64> = state.migrations.versions().collect();
+    let applied = if database_ok {
+        tokio::time::timeout(
+            HEALTH_CHECK_TIMEOUT,
+            sqlx::query_scalar::<_, i64>("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(&state.pool),
+        )
+        .await
+        .ok()
+        .and_then(Result::ok)
+    } else {
+        None
+    };
+    let migrations = MigrationHealth {
+        status: match &applied {
+            Some(applied) if expected.iter().all(|v| applied.contains(v)) => "up_to_date",
+            Some(_) => "pending",
+            None => "unknown",
+        },
+        applied: applied.as_ref().map(Vec::len),
+        expected: expected.len(),
+    };
+
+    let pagination_tokens = if database_ok {
+        tokio::time::timeout(
+            HEALTH_CHECK_TIMEOUT,
+            state.pagination_tokens.len(&state.pool),
+        )
+        .await
+        .ok()
+        .and_then(Result::ok)
+    } else {
+        None
+    };
+    let milk_tokens_available = state.limiter.lock().ok().map(|limiter| limiter.balance());
+
+    let status = match database.status {
+        "ok" if migrations.status == "up_to_date" => "ok",
+        "ok" | "degraded" => "degraded",
+        _ => "down",
+    };
+    let code = if status != "down" {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        code,
+        Json(HealthReport {
+            status,
+            database,
+            migrations,
+            milk_tokens_available,
+            pagination_tokens,
+        }),
+    )
+}
+
+// Prometheusのテキスト形式。バケットの残量をそのまま読むので、取り出しや補充がすぐ反映される
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "probes",
+    responses((status = 200, description = "Prometheus text format", body = String, content_type = "text/plain; version=0.0.4")),
+)]
+async fn metrics(State(state): State<AppState>) -> Response {
+    // ロックが壊れていてもパニックさせず、残量だけ出さない
+    let mut body = String::new();
+    if let Some(milk_available) = state.limiter.lock().ok().map(|limiter| limiter.balance()) {
+        body.push_str(&format!(
+            "# HELP milk_tokens_available Milk currently available in the bucket\n\
+             # TYPE milk_tokens_available gauge\n\
+             milk_tokens_available {}\n",
+            milk_available
+        ));
+    }
+    body.push_str(&format!(
+        "# HELP milk_tokens_capacity Maximum milk the bucket can hold\n\
+         # TYPE milk_tokens_capacity gauge\n\
+         milk_tokens_capacity {}\n",
+        BUCKET_SIZE
+    ));
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/-1/seek",
+    tag = "probes",
+    responses((status = 302, description = "Redirect to the video", headers(("Location" = String)))),
+)]
+async fn seek() -> (StatusCode, HeaderMap) {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::LOCATION,
+        "https://www.youtube.com/watch?v=9Gc4QTqslN4"
+            .parse()
+            .unwrap(),
+    );
+    (StatusCode::FOUND, headers)
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "shuttlings-cch24"),
+    paths(hello_world, live, ready, health, metrics, seek, openapi_json)
+)]
+struct ApiDoc;
+
+// 各日のドキュメントを1つにまとめる
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    doc.merge(day02::ApiDoc::openapi());
+    doc.merge(day05::ApiDoc::openapi());
+    doc.merge(day09::ApiDoc::openapi());
+    doc.merge(day12::ApiDoc::openapi());
+    doc.merge(day16::ApiDoc::openapi());
+    doc.merge(day19::ApiDoc::openapi());
+    doc.merge(day23::ApiDoc::openapi());
+    doc
+}
+
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "docs",
+    responses((status = 200, description = "This OpenAPI document", content_type = "application/json")),
+)]
+async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(openapi())
+}
+
+fn wants_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("application/json"))
+}
+
+async fn spa_fallback(assets_dir: PathBuf, uri: Uri) -> Response {
+    // 拡張子付きのパスは実ファイルへのリクエストなので、見つからなければ404
+    let last_segment = uri.path().rsplit('/').next().unwrap_or("");
+    if last_segment.contains('.') {
+        return AppError::NotFound.into_response();
+    }
+    match tokio::fs::read_to_string(assets_dir.join("index.html")).await {
+        Ok(index) => Html(index).into_response(),
+        Err(_) => AppError::NotFound.into_response(),
+    }
+}
+
+// ServeDirが付けるContent-LengthとLast-ModifiedからETagを作る
+fn asset_etag(response: &Response) -> Option<HeaderValue> {
+    let headers = response.headers();
+    let length: u64 = headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    let modified = DateTime::parse_from_rfc2822(headers.get(header::LAST_MODIFIED)?.to_str().ok()?)
+        .ok()?
+        .timestamp();
+    HeaderValue::from_str(&format!("\"{:x}-{:x}\"", length, modified)).ok()
+}
+
+async fn asset_cache_headers(request: Request, next: middleware::Next) -> Response {
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let mut response = next.run(request).await;
+
+    // HTMLは編集がすぐ反映されるよう毎回再検証させる
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    let cache_control = if is_html {
+        HeaderValue::from_static("no-cache")
+    } else {
+        HeaderValue::from_static("public, max-age=3600")
+    };
+
+    if response.status() == StatusCode::OK {
+        if let Some(etag) = asset_etag(&response) {
+            let matched = if_none_match
+                .as_ref()
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| {
+                    v.split(',')
+                        .map(|tag| tag.trim())
+                        .any(|tag| tag == "*" || tag.as_bytes() == etag.as_bytes())
+                });
+            if matched {
+                response = StatusCode::NOT_MODIFIED.into_response();
+            }
+            response.headers_mut().insert(header::ETAG, etag);
+        }
+    }
+    if response.status().is_success() || response.status() == StatusCode::NOT_MODIFIED {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, cache_control);
+    }
+    response
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "pretty" => Some(LogFormat::Pretty),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+async fn log_requests(request: Request, next: middleware::Next) -> Response {
+    // 呼び出し元がX-Request-Idを付けていればそれを使い、なければ採番する
+    let request_id = request
+        .headers()
+        .get("X-Request-Id")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    // 生のパスはIDを含んで種類が増えすぎるので、マッチしたルートのテンプレートを記録する
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        route = %route,
+        request_id = %request_id,
+    );
+    let started = std::time::Instant::now();
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+    span.in_scope(|| {
+        // AppErrorが変換したDBエラーはここでだけ記録する
+        if let Some(DatabaseErrorLog(error)) = response.extensions_mut().remove() {
+            tracing::error!(error = %error, "database error");
+        }
+        tracing::info!(
+            status = response.status().as_u16(),
+            latency_ms = started.elapsed().as_secs_f64() * 1000.0,
+            "request completed"
+        );
+    });
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert("X-Request-Id", value);
+    }
+    response
+}
+
+// 上限を超えたボディはaxumの既定のテキストではなく、上限を添えたJSONで返す
+pub(crate) async fn payload_too_large(
+    State(limit_bytes): State<usize>,
+    request: Request,
+    next: middleware::Next,
+) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return AppError::PayloadTooLarge { limit_bytes }.into_response();
+    }
+    response
+}
+
+// HTMLのレスポンスにだけセキュリティ系のヘッダーを付ける
+async fn security_headers(
+    State(csp): State<HeaderValue>,
+    request: Request,
+    next: middleware::Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    if is_html {
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_SECURITY_POLICY, csp);
+        headers.insert(
+            header::X_CONTENT_TYPE_OPTIONS,
+            HeaderValue::from_static("nosniff"),
+        );
+        headers.insert(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("strict-origin-when-cross-origin"),
+        );
+    }
+    response
+}
+
+async fn gateway_timeout(mut response: Response) -> Response {
+    if response.status() == StatusCode::REQUEST_TIMEOUT {
+        *response.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+    }
+    response
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+pub struct GracefulService {
+    router: Router,
+    pool: sqlx::PgPool,
+}
+
+impl GracefulService {
+    pub fn new(router: Router, pool: sqlx::PgPool) -> Self {
+        Self { router, pool }
+    }
+}
+
+#[shuttle_runtime::async_trait]
+impl shuttle_runtime::Service for GracefulService {
+    async fn bind(mut self, addr: SocketAddr) -> Result<(), shuttle_runtime::Error> {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(shuttle_runtime::CustomError::new)?;
+        self.serve(listener, shutdown_signal()).await
+    }
+}
+
+impl GracefulService {
+    async fn serve(
+        self,
+        listener: tokio::net::TcpListener,
+        shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+    ) -> Result<(), shuttle_runtime::Error> {
+        // シグナルを受けたら処理中のリクエストを捌き切ってから終了する
+        axum::serve(
+            listener,
+            self.router
+                .into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown)
+        .await
+        .map_err(shuttle_runtime::CustomError::new)?;
+
+        tracing::info!("shutting down, closing database pool");
+        self.pool.close().await;
+        Ok(())
+    }
+}
+
+// 起動時に実行するマイグレーション。readyとhealthも同じものと比べて未適用のものがないか確認する
+#[derive(Clone)]
+pub struct Migrations {
+    migrator: Arc<sqlx::migrate::Migrator>,
+    source: Arc<str>,
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Self {
+            migrator: Arc::new(sqlx::migrate!()),
+            source: Arc::from("./migrations"),
+        }
+    }
+}
+
+impl Migrations {
+    // テスト用にMIGRATIONS_DIRで別のディレクトリを指定できる（なければ埋め込みのものを使う）
+    pub async fn load() -> Result<Self, String> {
+        match std::env::var("MIGRATIONS_DIR") {
+            Ok(dir) => Self::from_dir(&dir).await,
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    async fn from_dir(dir: &str) -> Result<Self, String> {
+        let migrator = sqlx::migrate::Migrator::new(PathBuf::from(dir))
+            .await
+            .map_err(|e| format!("Failed to load migrations from {}: {}", dir, e))?;
+        Ok(Self {
+            migrator: Arc::new(migrator),
+            source: Arc::from(dir),
+        })
+    }
+
+    fn versions(&self) -> impl Iterator<Item = i64> + '_ {
+        self.migrator.iter().map(|m| m.version)
+    }
+}
+
+pub async fn run_migrations(pool: &sqlx::PgPool, migrations: &Migrations) -> Result<(), String> {
+    let Migrations { migrator, source } = migrations;
+    if migrator.iter().next().is_none() {
+        tracing::warn!("no migrations found in {}, skipping", source);
+        return Ok(());
+    }
+
+    migrator.run(pool).await.map_err(|e| {
+        // どのマイグレーションで失敗したかが分かるように、ファイルの説明も付ける
+        let version = match &e {
+            sqlx::migrate::MigrateError::ExecuteMigration(_, version)
+            | sqlx::migrate::MigrateError::VersionMismatch(version)
+            | sqlx::migrate::MigrateError::Dirty(version) => Some(*version),
+            _ => None,
+        };
+        match version.and_then(|v| migrator.iter().find(|m| m.version == v)) {
+            Some(migration) => format!(
+                "Failed to run migration {} ({}) from {}: {}",
+                migration.version, migration.description, source, e
+            ),
+            None => format!("Failed to run migrations from {}: {}", source, e),
+        }
+    })
+}
+
+// 各モジュールのテストから使う、Shuttleなしでルーターを動かすための補助
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+    use axum::body::{Body, Bytes};
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    pub(crate) struct TestResponse {
+        pub(crate) status: StatusCode,
+        pub(crate) headers: HeaderMap,
+        pub(crate) body: Bytes,
+    }
+
+    impl TestResponse {
+        pub(crate) fn text(&self) -> String {
+            String::from_utf8(self.body.to_vec()).unwrap()
+        }
+
+        pub(crate) fn json(&self) -> serde_json::Value {
+            serde_json::from_slice(&self.body).unwrap()
+        }
+    }
+
+    // DBを使わないテスト用。最初のクエリまで接続しないので、DBがなくても作れる
+    pub(crate) fn lazy_pool() -> sqlx::PgPool {
+        sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap()
+    }
+
+    pub(crate) fn router(pool: sqlx::PgPool) -> Router {
+        router_with(pool, AppConfig::default())
+    }
+
+    pub(crate) fn router_with(pool: sqlx::PgPool, config: AppConfig) -> Router {
+        build_router(AppState::new(pool, config))
+    }
+
+    pub(crate) async fn send(router: &Router, request: Request) -> TestResponse {
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    // SecretStoreはシリアライズ経由でしか作れないので、JSONのマップから組み立てる
+    pub(crate) fn secrets(entries: &[(&str, &str)]) -> SecretStore {
+        let map: serde_json::Map<String, serde_json::Value> = entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), serde_json::Value::from(*value)))
+            .collect();
+        serde_json::from_value(serde_json::Value::Object(map)).unwrap()
+    }
+
+    pub(crate) fn get(uri: &str) -> Request {
+        request("GET", uri, None, Body::empty())
+    }
+
+    pub(crate) fn post(uri: &str, content_type: &str, body: impl Into<Body>) -> Request {
+        request("POST", uri, Some(content_type), body.into())
+    }
+
+    pub(crate) fn post_json(uri: &str, value: serde_json::Value) -> Request {
+        post(uri, "application/json", value.to_string())
+    }
+
+    pub(crate) fn request(
+        method: &str,
+        uri: &str,
+        content_type: Option<&str>,
+        body: Body,
+    ) -> Request {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(content_type) = content_type {
+            builder = builder.header(CONTENT_TYPE, content_type);
+        }
+        builder.body(body).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        run_migrations, testing::*, AppConfig, BodyLimits, GracefulService, LogFormat, Migrations,
+    };
+    use axum::{
+        body::Body,
+        http::{header, header::CONTENT_TYPE, StatusCode},
+    };
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn hello_world_responds() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "Hello, bird!");
+    }
+
+    #[tokio::test]
+    async fn unknown_route_is_not_found() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/no-such-day")).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+
+    fn assets_config(dir: &std::path::Path) -> AppConfig {
+        AppConfig {
+            assets_dir: dir.to_path_buf(),
+            ..AppConfig::default()
+        }
+    }
+
+    fn http_get(addr: std::net::SocketAddr) -> std::io::Result<String> {
+        use std::io::{Read, Write};
+        let mut stream = std::net::TcpStream::connect(addr)?;
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        Ok(response)
+    }
+
+    #[sqlx::test]
+    async fn shutdown_drains_and_closes_the_pool(pool: sqlx::PgPool) {
+        let service = GracefulService::new(router(pool.clone()), pool.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(service.serve(listener, async {
+            shutdown_rx.await.ok();
+        }));
+
+        let response = tokio::task::spawn_blocking(move || http_get(addr))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("Hello, bird!"));
+
+        shutdown_tx.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("shutdown did not complete")
+            .unwrap()
+            .unwrap();
+        assert!(pool.is_closed());
+
+        // リスナーは閉じているので、新しい接続は受け付けない
+        let result = tokio::task::spawn_blocking(move || http_get(addr))
+            .await
+            .unwrap();
+        assert!(result.is_err());
+    }
+
+    #[sqlx::test]
+    async fn assets_fall_back_to_the_index(pool: sqlx::PgPool) {
+        let dir = std::env::temp_dir().join(format!("assets-fallback-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), "<p>index</p>").unwrap();
+        std::fs::write(dir.join("app.js"), "console.log('hi');").unwrap();
+        let router = router_with(pool, assets_config(&dir));
+
+        let response = send(&router, get("/assets/app.js")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "console.log('hi');");
+
+        // クライアント側のルートはindex.htmlで解決する
+        let response = send(&router, get("/assets/presents/42")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "<p>index</p>");
+
+        let response = send(&router, get("/assets/missing.css")).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[sqlx::test]
+    async fn head_is_served_for_get_routes(pool: sqlx::PgPool) {
+        let dir = std::env::temp_dir().join(format!("assets-head-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("app.js"), "console.log('hi');").unwrap();
+        let router = router_with(pool, assets_config(&dir));
+
+        let response = send(&router, request("HEAD", "/-1/seek", None, Body::empty())).await;
+        assert_eq!(response.status, StatusCode::FOUND);
+        assert!(response.headers.contains_key(header::LOCATION));
+        assert!(response.body.is_empty());
+
+        let response = send(&router, request("HEAD", "/19/list", None, Body::empty())).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers[CONTENT_TYPE], "application/json");
+        assert!(response.body.is_empty());
+
+        let response = send(
+            &router,
+            request("HEAD", "/assets/app.js", None, Body::empty()),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.headers[header::CONTENT_LENGTH], "18");
+        assert!(response.body.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn requests_carry_a_request_id() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let generated = response.headers["x-request-id"].to_str().unwrap();
+        assert!(Uuid::parse_str(generated).is_ok());
+
+        let mut request = get("/");
+        request
+            .headers_mut()
+            .insert("X-Request-Id", "abc-123".parse().unwrap());
+        let response = send(&router, request).await;
+        assert_eq!(response.headers["x-request-id"], "abc-123");
+    }
+
+    #[test]
+    fn log_formats_are_parsed_by_name() {
+        assert!(matches!(
+            LogFormat::from_name("json"),
+            Some(LogFormat::Json)
+        ));
+        assert!(matches!(
+            LogFormat::from_name("pretty"),
+            Some(LogFormat::Pretty)
+        ));
+        assert!(LogFormat::from_name("yaml").is_none());
+    }
+
+    #[sqlx::test]
+    async fn live_does_not_touch_the_database(pool: sqlx::PgPool) {
+        let router = router(pool.clone());
+        pool.close().await;
+        let response = send(&router, get("/live")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "OK");
+    }
+
+    #[sqlx::test]
+    async fn ready_fails_without_a_database(pool: sqlx::PgPool) {
+        let router = router(pool.clone());
+        assert_eq!(send(&router, get("/ready")).await.status, StatusCode::OK);
+        pool.close().await;
+        let response = send(&router, get("/ready")).await;
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.text(), "Not ready: database unavailable");
+    }
+
+    #[sqlx::test]
+    async fn ready_waits_for_every_migration(pool: sqlx::PgPool) {
+        let router = router(pool.clone());
+        let response = send(&router, get("/ready")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.text(), "OK");
+
+        sqlx::query(
+            "DELETE FROM _sqlx_migrations WHERE version = (SELECT MAX(version) FROM _sqlx_migrations)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let response = send(&router, get("/ready")).await;
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.text(), "Not ready: migrations pending");
+    }
+
+    #[sqlx::test(migrations = false)]
+    async fn failed_migration_names_version_and_description(pool: sqlx::PgPool) {
+        let dir = std::env::temp_dir().join(format!("migrations-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("0001_create_gifts.sql"),
+            "CREATE TABLE gifts (id INT);",
+        )
+        .unwrap();
+        std::fs::write(dir.join("0002_broken_table.sql"), "CREATE TABLE (;").unwrap();
+        let dir_name = dir.to_str().unwrap().to_string();
+        let migrations = Migrations::from_dir(&dir_name).await.unwrap();
+
+        let error = run_migrations(&pool, &migrations).await.unwrap_err();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(
+            error.starts_with(&format!(
+                "Failed to run migration 2 (broken table) from {}:",
+                dir_name
+            )),
+            "{}",
+            error
+        );
+    }
+
+    #[sqlx::test]
+    async fn assets_revalidate_with_etags(pool: sqlx::PgPool) {
+        let dir = std::env::temp_dir().join(format!("assets-etag-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), "<p>index</p>").unwrap();
+        std::fs::write(dir.join("app.js"), "console.log('hi');").unwrap();
+        let router = router_with(pool, assets_config(&dir));
+
+        let response = send(&router, get("/assets/app.js")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.headers[header::CACHE_CONTROL],
+            "public, max-age=3600"
+        );
+        let etag = response.headers[header::ETAG].clone();
+
+        let mut revalidate = get("/assets/app.js");
+        revalidate
+            .headers_mut()
+            .insert(header::IF_NONE_MATCH, etag.clone());
+        let response = send(&router, revalidate).await;
+        assert_eq!(response.status, StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers[header::ETAG], etag);
+        assert!(response.body.is_empty());
+
+        let mut stale = get("/assets/app.js");
+        stale
+            .headers_mut()
+            .insert(header::IF_NONE_MATCH, "\"0-0\"".parse().unwrap());
+        assert_eq!(send(&router, stale).await.status, StatusCode::OK);
+
+        let response = send(&router, get("/assets/index.html")).await;
+        assert_eq!(response.headers[header::CACHE_CONTROL], "no-cache");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[sqlx::test]
+    async fn metrics_gauge_follows_milk_withdrawals(pool: sqlx::PgPool) {
+        let router = router(pool);
+        let response = send(&router, get("/metrics")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(
+            response.headers[CONTENT_TYPE],
+            "text/plain; version=0.0.4; charset=utf-8"
+        );
+        let text = response.text();
+        assert!(text.contains("\nmilk_tokens_available 5\n"), "{}", text);
+        assert!(text.contains("\nmilk_tokens_capacity 5\n"), "{}", text);
+
+        send(&router, post("/9/milk", "text/plain", "")).await;
+        let text = send(&router, get("/metrics")).await.text();
+        assert!(text.contains("\nmilk_tokens_available 4\n"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn metrics_survive_a_poisoned_milk_limiter() {
+        let state = super::AppState::new(lazy_pool(), AppConfig::default());
+        let limiter = state.limiter.clone();
+        std::thread::spawn(move || {
+            let _guard = limiter.lock().unwrap();
+            panic!("poison the milk limiter");
+        })
+        .join()
+        .unwrap_err();
+        let router = super::build_router(state);
+
+        let response = send(&router, get("/metrics")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let body = response.text();
+        assert!(!body.contains("milk_tokens_available"));
+        assert!(body.contains("milk_tokens_capacity 5\n"));
+    }
+
+    #[sqlx::test]
+    async fn health_reports_each_dependency(pool: sqlx::PgPool) {
+        let response = send(&router(pool), get("/health")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let report = response.json();
+        assert_eq!(report["status"], "ok");
+        assert_eq!(report["database"]["status"], "ok");
+        assert!(report["database"]["latency_ms"].is_number());
+        assert_eq!(report["migrations"]["status"], "up_to_date");
+        assert_eq!(report["milk_tokens_available"], 5);
+        assert_eq!(report["pagination_tokens"], 0);
+
+        let response = send(&router(lazy_pool()), get("/health")).await;
+        assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+        let report = response.json();
+        assert_eq!(report["status"], "down");
+        assert_eq!(report["migrations"]["status"], "unknown");
+        assert!(report["pagination_tokens"].is_null());
+    }
+
+    #[sqlx::test]
+    async fn health_compares_against_the_loaded_migrations(pool: sqlx::PgPool) {
+        let response = send(&router(pool.clone()), get("/health")).await;
+        assert_eq!(response.json()["migrations"]["status"], "up_to_date");
+
+        // 埋め込みのものにない新しいマイグレーションがあれば未適用として扱う
+        let dir = std::env::temp_dir().join(format!("migrations-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("9999_future.sql"), "SELECT 1;").unwrap();
+        let migrations = Migrations::from_dir(dir.to_str().unwrap()).await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let config = AppConfig {
+            migrations,
+            ..AppConfig::default()
+        };
+
+        let response = send(&router_with(pool, config), get("/health")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.json()["migrations"]["status"], "pending");
+        assert_eq!(response.json()["migrations"]["expected"], 1);
+    }
+
+    #[sqlx::test]
+    async fn slow_database_is_degraded_not_down(pool: sqlx::PgPool) {
+        let single = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(1)
+            .connect_with((*pool.connect_options()).clone())
+            .await
+            .unwrap();
+        let _held = single.acquire().await.unwrap();
+
+        let response = send(&router(single.clone()), get("/health")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let report = response.json();
+        assert_eq!(report["status"], "degraded");
+        assert_eq!(report["database"]["status"], "degraded");
+        assert_eq!(report["migrations"]["status"], "unknown");
+    }
+
+    #[tokio::test]
+    async fn swagger_ui_is_served_locally() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/docs/")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(!response.text().contains("unpkg"));
+
+        let response = send(&router, get("/docs/swagger-initializer.js")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(response.text().contains("/openapi.json"));
+
+        let response = send(&router, get("/docs/swagger-ui-bundle.js")).await;
+        assert_eq!(response.status, StatusCode::OK);
+    }
+
+    // ルートにないメソッドを送ると、axumはそのパスで使えるメソッドをAllowに入れて405を返す。
+    // これで文書化したパスとメソッドがすべてルーターに登録されていることを確かめる
+    #[tokio::test]
+    async fn openapi_document_matches_the_router() {
+        let router = router(lazy_pool());
+        let response = send(&router, get("/openapi.json")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let doc: utoipa::openapi::OpenApi = serde_json::from_slice(&response.body).unwrap();
+        assert!(response.json()["openapi"]
+            .as_str()
+            .unwrap()
+            .starts_with("3."));
+        assert!(doc.paths.paths.len() > 40, "{:?}", doc.paths.paths.keys());
+
+        for (path, item) in &doc.paths.paths {
+            let uri = path
+                .split('/')
+                .map(|segment| {
+                    if segment.starts_with('{') {
+                        "1"
+                    } else {
+                        segment
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            let response = send(&router, request("TRACE", &uri, None, Body::empty())).await;
+            assert_eq!(response.status, StatusCode::METHOD_NOT_ALLOWED, "{}", path);
+            let allow = response.headers["allow"].to_str().unwrap();
+            let documented = [
+                ("GET", &item.get),
+                ("POST", &item.post),
+                ("PUT", &item.put),
+                ("PATCH", &item.patch),
+                ("DELETE", &item.delete),
+            ];
+            for (method, operation) in documented {
+                if operation.is_some() {
+                    assert!(allow.contains(method), "{} {} not routed", method, path);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_hit_the_default_limit() {
+        let config = AppConfig {
+            body_limits: BodyLimits {
+                default_bytes: 32,
+                gift_bytes: 32,
+            },
+            ..AppConfig::default()
+        };
+        let router = router_with(lazy_pool(), config);
+
+        let response = send(
+            &router,
+            post("/5/manifest", "application/toml", "#".repeat(64)),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+        let error = response.json();
+        assert_eq!(error["error"]["code"], "payload_too_large");
+        assert_eq!(error["error"]["details"]["limit_bytes"], 32);
+
+        let response = send(
+            &router,
+            post_json(
+                "/19/draft",
+                serde_json::json!({"author": "Santa", "quote": "#".repeat(64)}),
+            ),
+        )
+        .await;
+        assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+        let error = response.json();
+        assert_eq!(error["error"]["code"], "payload_too_large");
+        assert_eq!(error["error"]["details"]["limit_bytes"], 32);
+    }
+}
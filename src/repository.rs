@@ -0,0 +1,165 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use std::{future::Future, time::Duration};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::day19::Quote;
+
+const RETRY_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(50);
+
+// 接続が切れた・シリアライズに失敗したなど、やり直せば通る可能性のあるエラー
+fn is_retryable(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+        // serialization_failure, deadlock_detected
+        sqlx::Error::Database(db_err) => {
+            matches!(db_err.code().as_deref(), Some("40001" | "40P01"))
+        }
+        _ => false,
+    }
+}
+
+// 一時的なエラーは待ち時間を倍にしながらやり直す
+// 二重に実行されると困るので、読み取りだけのクエリに使うこと
+pub async fn with_retry<T, F, Fut>(mut op: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(e) if attempt + 1 < RETRY_ATTEMPTS && is_retryable(&e) => {
+                tracing::warn!(error = ?e, attempt, "retrying transient database error");
+                tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize, ToSchema)]
+pub struct AuthorStats {
+    pub author: String,
+    pub count: i64,
+    pub earliest: DateTime<Utc>,
+    pub latest: DateTime<Utc>,
+}
+
+pub async fn find_quote(pool: &PgPool, id: Uuid) -> Result<Option<Quote>, sqlx::Error> {
+    sqlx::query_as::<_, Quote>("SELECT * FROM quotes WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn quote_exists(conn: &mut PgConnection, id: Uuid) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM quotes WHERE id = $1)")
+        .bind(id)
+        .fetch_one(conn)
+        .await
+}
+
+pub async fn count_quotes(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COUNT(*) FROM quotes")
+        .fetch_one(pool)
+        .await
+}
+
+// 著者ごとの件数と最古・最新の作成日時を1回の集計クエリで取得する
+pub async fn author_stats(pool: &PgPool, min_count: i64) -> Result<Vec<AuthorStats>, sqlx::Error> {
+    sqlx::query_as::<_, AuthorStats>(
+        "SELECT author, COUNT(*) AS count, MIN(created_at) AS earliest, MAX(created_at) AS latest \
+         FROM quotes GROUP BY author HAVING COUNT(*) >= $1 \
+         ORDER BY count DESC, author ASC",
+    )
+    .bind(min_count)
+    .fetch_all(pool)
+    .await
+}
+
+// 引用と履歴をまとめて削除し、削除した引用の件数を返す
+pub async fn clear_quotes(conn: &mut PgConnection) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM quotes")
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM quote_revisions")
+        .execute(&mut *conn)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[derive(sqlx::FromRow, Serialize, ToSchema)]
+pub struct AuthorSummary {
+    pub author: String,
+    pub count: i64,
+    pub latest: DateTime<Utc>,
+}
+
+// 作者名の昇順で、afterより後ろの作者を最大limit件返す（limitがNoneなら全件）
+pub async fn list_authors(
+    pool: &PgPool,
+    prefix_pattern: Option<&str>,
+    after: Option<&str>,
+    limit: Option<i64>,
+) -> Result<Vec<AuthorSummary>, sqlx::Error> {
+    sqlx::query_as::<_, AuthorSummary>(
+        "SELECT author, COUNT(*) AS count, MAX(created_at) AS latest FROM quotes \
+         WHERE ($1::text IS NULL OR author ILIKE $1 ESCAPE '\\') \
+         AND ($2::text IS NULL OR author > $2) \
+         GROUP BY author ORDER BY author ASC LIMIT $3",
+    )
+    .bind(prefix_pattern)
+    .bind(after)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{with_retry, RETRY_ATTEMPTS};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn retries_transient_errors_until_success() {
+        let calls = AtomicU32::new(0);
+        let result = with_retry(|| async {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 => Err(sqlx::Error::PoolTimedOut),
+                n => Ok(n),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_attempt() {
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = with_retry(|| async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::PoolTimedOut)
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::PoolTimedOut)));
+        assert_eq!(calls.load(Ordering::SeqCst), RETRY_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_other_errors() {
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = with_retry(|| async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::RowNotFound)
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::RowNotFound)));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}